@@ -0,0 +1,94 @@
+//! Compare scanning the pairs of heaps with `Scan` against the loop over them, both on their own
+//! and in the computation of a game.
+//!
+//! Run with `cargo bench --bench xor_scan`.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use octal::octal::{BitSet, Game, Nimber, VecBin};
+
+fn time<T>(name: &str, mut f: impl FnMut() -> T) -> Duration {
+    let start = Instant::now();
+    let mut runs = 0;
+    while start.elapsed() < Duration::from_secs(1) {
+        black_box(f());
+        runs += 1;
+    }
+    let each = start.elapsed() / runs;
+    println!("{:40} {:>12?}", name, each);
+    each
+}
+
+/// The loop of `prove` before `Scan`: every pair is looked up in the bitset.
+fn first_missing_loop<N: Nimber>(
+    g: &[N],
+    m: usize,
+    mex: &VecBin,
+    first_common: usize,
+) -> Option<usize> {
+    (1..=m / 2).find(|&j| {
+        let loc = (g[j] ^ g[m - j]).to_usize();
+        loc < first_common && !mex.get(loc)
+    })
+}
+
+fn scan<N: Nimber>(width: &str) {
+    // even values, so the odd values looked for are never found and all the pairs are checked
+    let len = 1 << 20;
+    let g: Vec<N> = (0..len)
+        .map(|k| N::from_usize(((k * 2654435761) >> 7) % 64 * 2))
+        .collect();
+    let g_rev: Vec<N> = g.iter().rev().copied().collect();
+    let first_common = 64;
+    let mut mex = VecBin::make(first_common + 1);
+    for w in 0..first_common {
+        if w % 16 != 3 {
+            mex.set_bit(w);
+        }
+    }
+    let wanted: Vec<N> = (0..first_common)
+        .filter(|&w| !mex.get(w))
+        .map(N::from_usize)
+        .collect();
+
+    let m = len - 1;
+    let looped = time(&format!("loop over {} pairs, u{}", m / 2, width), || {
+        first_missing_loop(&g, m, &mex, first_common)
+    });
+    let scanned = time(&format!("scan of {} pairs, u{}", m / 2, width), || {
+        N::first_xor_in(&g[1..=m / 2], &g_rev[len - m..], &wanted)
+    });
+    println!(
+        "{:40} {:>11.2}x",
+        "speedup",
+        looped.as_secs_f64() / scanned.as_secs_f64()
+    );
+}
+
+fn game(rules_str: &str, max: usize) {
+    for reversed in [false, true] {
+        time(
+            &format!("{} up to {}, reversed {}", rules_str, max, reversed),
+            || {
+                let mut g = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
+                if reversed {
+                    g.nimbers.use_reversed();
+                }
+                g.init();
+                for n in g.rules.len()..max {
+                    g.calc_rc(n).unwrap();
+                }
+                g.nimbers.g.get(max - 1)
+            },
+        );
+    }
+}
+
+fn main() {
+    scan::<u8>("8");
+    scan::<u16>("16");
+    scan::<u32>("32");
+    scan::<u64>("64");
+    game("0.16", 1 << 16);
+    game("0.034", 1 << 16);
+}
@@ -1,83 +1,42 @@
 use std::env;
-use std::fs;
-use std::io::Read;
 use std::path::Path;
 
-pub mod octal;
+use octal::octal;
 
-fn load(max: usize, path: &Path) -> Vec<octal::Nimber> {
-    println!("Reading nimbers from {:?}", path);
+use octal::{History, Nimber};
 
-    let nimber_bytes = (octal::Nimber::BITS / u8::BITS) as usize;
-    let mut nimbers = vec![0 as octal::Nimber; max];
-    let mut buf: Vec<u8> = Vec::with_capacity(max * nimber_bytes);
-
-    fs::OpenOptions::new()
-        .read(true)
-        .open(path)
-        .unwrap()
-        .read_to_end(&mut buf)
-        .unwrap();
-
-    for i in 0..max {
-        let mut n: octal::Nimber = 0;
-        for b in 0..nimber_bytes {
-            let loc = (i * nimber_bytes) + (nimber_bytes - b) - 1;
-            n += (buf[loc] as octal::Nimber) << (b * 8);
+/// The value of the heap i, from the rings saved by the tail mode.
+fn at<N: Nimber>(history: &History<N>, i: usize) -> N {
+    match history.read(i) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("unable to read G({}): {}", i, e);
+            std::process::exit(1);
         }
-        nimbers[i] = n;
     }
-    nimbers
 }
 
-struct Mmap {
-    buf: Vec<octal::Nimber>,
-    end: usize,
-    path: String,
-}
-
-impl Mmap {
-    fn new(path: String, max_tail_memory: usize, end: usize) -> Self {
-        let mut p = path.clone();
-        p.push_str(&end.to_string());
-        Self {
-            path,
-            end,
-            buf: load(max_tail_memory, &Path::new(&p)),
-        }
-    }
-
-    fn at<'a>(&mut self, i: usize) -> octal::Nimber {
-        let len = self.buf.len();
-        if !(self.end <= i + len && i < self.end) {
-            self.end = ((i / len) + 1) * len;
-            let mut p = self.path.clone();
-            p.push_str(&self.end.to_string());
-            self.buf = load(len, &Path::new(&p))
-        }
-
-        return self.buf[i % len];
-    }
-}
-
-struct Mem {
-    left: Mmap,
-    right: Mmap,
-}
-
-impl Mem {
-    fn new(path: String, max_tail_memory: usize, end: usize) -> Self {
-        Self {
-            left: Mmap::new(path.to_owned(), max_tail_memory, end),
-            right: Mmap::new(path, max_tail_memory, end),
-        }
-    }
+/// Settings of the period search over the rings on disk.
+#[derive(Clone, Copy)]
+struct Search {
+    /// Periods checked in each pass over the rings.
+    batch: usize,
+    /// Rings kept in memory.
+    cache: usize,
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
     let rules_str = if args.len() > 1 { &args[1] } else { "0.034" };
+    let code: octal::GameCode = match rules_str.parse() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("invalid game code {:?}: {}", rules_str, e);
+            std::process::exit(1);
+        }
+    };
+    let rules_str = &code.to_string();
 
     let max_full_memory = if args.len() > 2 {
         args[2].parse::<usize>().unwrap()
@@ -103,40 +62,89 @@ fn main() {
     // println!("total: {:?}", start.elapsed());
 
     let mut last = 0;
-    let mut hm = vec![];
 
     // let mut total: usize = 0;
 
-    let path = format!("{}/nimbers_{}_", dir, rules_str);
-
-
-    let achim: Vec<usize> = if args.len() > 5 {
-        args[5..]
-            .iter()
-            .map(|x| x.parse::<usize>().unwrap())
-            .collect()
-    } else {
-        vec![]
-    };
+    let dir = Path::new(&dir);
+
+    // the heaps to print the frequencies at, and the options of the period search: the periods
+    // checked in each pass (--batch=<periods>) and the rings kept in memory (--cache=<rings>)
+    let mut achim = vec![];
+    let mut batch = max_tail_memory;
+    let mut cache = None;
+    for arg in args.iter().skip(5) {
+        if let Some(x) = arg.strip_prefix("--batch=") {
+            batch = x.parse::<usize>().unwrap();
+        } else if let Some(x) = arg.strip_prefix("--cache=") {
+            cache = Some(x.parse::<usize>().unwrap());
+        } else {
+            achim.push(arg.parse::<usize>().unwrap());
+        }
+    }
+    if batch == 0 {
+        eprintln!("at least one period has to be checked in each pass");
+        std::process::exit(1);
+    }
+    // the rings of the window of a batch, and the ones at the end
+    let cache = cache.unwrap_or(batch.div_ceil(max_tail_memory) + 4);
+    let search = Search { batch, cache };
 
     for i in (max_full_memory..).step_by(max_tail_memory) {
-        let p = path.to_owned() + &i.to_string();
-        let pth = Path::new(&p);
-
-        if pth.exists() {
+        if octal::checkpoint::nimbers_path(dir, rules_str, i).exists() {
             last = i;
         } else {
             break;
         }
     }
 
-    let mut mem = Mem::new(path, max_tail_memory, max_tail_memory);
+    // the nimbers are read with the widest width any of the rings was saved with (the tail mode
+    // promotes them when a value does not fit), or the narrowest one holding them if they were
+    // packed
+    let mut width = u8::BITS;
+    for i in (max_tail_memory..=std::cmp::max(last, max_tail_memory)).step_by(max_tail_memory) {
+        let path = octal::checkpoint::nimbers_path(dir, rules_str, i);
+        match octal::chunk::read_header(&path) {
+            Ok(header) => width = std::cmp::max(width, header.bits.next_power_of_two()),
+            Err(e) => {
+                eprintln!("unable to read {:?}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    match width {
+        8 => run::<u8>(&code, dir, max_tail_memory, last, &achim, search),
+        16 => run::<u16>(&code, dir, max_tail_memory, last, &achim, search),
+        32 => run::<u32>(&code, dir, max_tail_memory, last, &achim, search),
+        64 => run::<u64>(&code, dir, max_tail_memory, last, &achim, search),
+        _ => run::<u128>(&code, dir, max_tail_memory, last, &achim, search),
+    }
+}
 
-    for i in 1..=last {
-        let n = mem.right.at(i) as usize;
+fn run<N: Nimber>(
+    code: &octal::GameCode,
+    dir: &Path,
+    max_tail_memory: usize,
+    last: usize,
+    achim: &[usize],
+    search: Search,
+) {
+    let mut hm = vec![];
+    let history = History::<N>::new(dir.to_path_buf(), code.to_string(), max_tail_memory)
+        .with_capacity(search.cache);
+
+    // the ring saved at last ends with the heap last - 1
+    for i in 1..last {
+        // the first heap of each ring reads it from disk
+        if i == 1 || i % max_tail_memory == 0 {
+            let end = (i / max_tail_memory + 1) * max_tail_memory;
+            let path = octal::checkpoint::nimbers_path(dir, &code.to_string(), end);
+            println!("Reading nimbers from {:?}", path);
+        }
+        let n = at(&history, i).to_usize();
 
         if n >= hm.len() {
-            hm.resize(n + 1, 0 as usize);
+            hm.resize(n + 1, 0_usize);
         }
 
         hm[n] += 1;
@@ -146,32 +154,53 @@ fn main() {
                 println!("{} {}", n, cnt);
             }
             println!("{} {}", i, (i as f64).log2());
-            println!("");
+            println!();
         }
     }
 
-
-    let mut found = false;
-
-    for period in 1..=(last / 2) {
-        if period % mem.right.buf.len() == 0 {
-            println!("{}", period);
-        }
-        let mut start = last - period;
-
-        while start > 0 && mem.left.at(start - 1) == mem.right.at(start - 1 + period) {
-            start -= 1;
+    // a read error of the history stops the search with a panic; games with a repeating code
+    // may be arithmetic periodic instead
+    let (rules, repeating) = (code.rules(), code.repeating_rules());
+    if !repeating.is_empty() {
+        // the sufficient condition only confirms a saltus of 0 when the values are xor-ed
+        let divides = rules
+            .iter()
+            .chain(&repeating)
+            .any(|r| r.divide || r.divide3);
+        match octal::period::find_arithmetic_period(
+            &history,
+            &rules,
+            &repeating,
+            last,
+            search.batch,
+        ) {
+            Some((start, period, saltus)) => {
+                println!("period start: {}\n", start);
+                println!("period: {}\n", period);
+                println!("saltus: {}\n", saltus);
+            }
+            None if divides => {
+                println!("no period :( (only a saltus of 0 is checked for games dividing heaps)")
+            }
+            None => println!("no period :("),
         }
+        return;
+    }
 
-        if last >= 2 * start + 2 * period + rules_str.len() - 1 {
+    match octal::period::find_period_in_batches(&history, &rules, last, search.batch) {
+        octal::period::Search::Period(start, period) => {
             println!("period start: {}\n", start);
             println!("period: {}\n", period);
-            found = true;
-            break;
         }
-    }
-
-    if !found {
-        println!("no period :(");
+        octal::period::Search::Candidate(Some((start, period))) => {
+            println!("candidate period start: {}\n", start);
+            println!("candidate period: {}\n", period);
+            println!(
+                "repeated for {} heaps, confirmed after {} heaps if it goes on",
+                last.saturating_sub(start + period),
+                octal::periodicity_bound(&rules, start, period)
+            );
+        }
+        octal::period::Search::Candidate(None) => println!("no period :("),
     }
 }
@@ -0,0 +1,3 @@
+//! The solver of octal games, shared by the binaries and the benchmarks.
+
+pub mod octal;
@@ -1,54 +1,100 @@
 // use game::gen_rares;
 use std::env;
-use std::fs;
-use std::io::{Read, Write};
 use std::path::Path;
 use std::time::Instant;
-use glob;
 
-pub mod octal;
+use octal::octal;
 
-fn save(n: usize, rules_str: &str, buf: &[u8]) {
-    let path = format!("nimbers_{}_{}", rules_str, n);
+use octal::mapped::SYNC_EVERY;
+use octal::period::PeriodDetector;
+use octal::{BitSet, FixedBin, Game, Nimber, Overflow, VecBin};
 
-    fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .open(path)
-        .unwrap()
-        .write(buf)
-        .unwrap();
+/// Settings of the algorithm, which do not change the values computed.
+#[derive(Clone, Copy)]
+struct Options {
+    /// Levels of the sparse space.
+    levels: usize,
+    /// Threads used for a single heap.
+    threads: usize,
+    /// Heaps of the front computed at once.
+    speculation: usize,
+    /// Keep the sequence reversed as well, to scan the pairs of heaps with SIMD.
+    simd: bool,
+    /// Keep the front in a file mapped into memory, and resume computing it from that file.
+    mapped: bool,
+    /// Keep the nimbers in memory bit-packed.
+    packed: bool,
+    /// Longest period looked for while the nimbers are computed.
+    max_period: usize,
 }
 
-fn load(max: usize, path: &Path) -> Vec<octal::Nimber> {
-    println!("Reading nimbers from {:?}", path);
-
-    let nimber_bytes = (octal::Nimber::BITS / u8::BITS) as usize;
-    let mut nimbers = vec![0 as octal::Nimber; max];
-    let mut buf: Vec<u8> = Vec::with_capacity(max * nimber_bytes);
-
-    fs::OpenOptions::new()
-        .read(true)
-        .open(path)
-        .unwrap()
-        .read_to_end(&mut buf)
-        .unwrap();
-
-    for i in 0..max {
-        let mut n: octal::Nimber = 0;
-        for b in 0..nimber_bytes {
-            let loc = (i * nimber_bytes) + (nimber_bytes - b) - 1;
-            n += (buf[loc] as octal::Nimber) << (b * 8);
+/// Continue computing the game with wider nimbers, after the value of a heap did not fit.
+fn promote<N: Nimber, B: BitSet>(g: Game<N, B>, overflow: Overflow) -> Game<N::Wider, B> {
+    assert!(N::Wider::BITS > N::BITS, "{}", overflow);
+    println!(
+        "{}, promoting the nimbers to {} bits",
+        overflow,
+        N::Wider::BITS
+    );
+    g.widen()
+}
+
+/// Print the period found, in the format of `Game::check_period`.
+fn report_period(start: usize, period: usize, count: usize) {
+    println!("period start: {}\n", start);
+    println!("period: {}\n", period);
+    println!("confirmed after {} heaps", count);
+}
+
+/// Compute the nimbers in tail mode from heap `first` on, saving a checkpoint every
+/// max_tail_memory heaps, until the detector finds a period.  `skipped` is the heap the run
+/// started computing from.
+fn tail<N: Nimber, B: BitSet>(
+    mut g: Game<N, B>,
+    skipped: usize,
+    first: usize,
+    dir: &Path,
+    start: &Instant,
+    mut detector: Option<PeriodDetector>,
+) {
+    let max_tail_memory = g.nimbers.g_back.len();
+    g.use_history(dir);
+    // the periods are checked within the tail ring
+    if let Some(detector) = &mut detector {
+        detector.truncate(max_tail_memory);
+    }
+
+    for n in first.. {
+        if n % max_tail_memory == 0 {
+            g.save_checkpoint(dir, n).unwrap();
+        }
+        if let Err(overflow) = g.calc_rc_back(n) {
+            return tail(promote(g, overflow), skipped, n, dir, start, detector);
+        }
+        g.occasional_info_back(skipped, n, start);
+
+        if let Some((period_start, period)) = detector
+            .as_mut()
+            .and_then(|d| d.update(&g.sequence(n + 1), n + 1))
+        {
+            report_period(period_start, period, n + 1);
+            g.dump_stats_back(skipped, n, start);
+            return;
         }
-        nimbers[i] = n;
     }
-    nimbers
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
     let rules_str = if args.len() > 1 { &args[1] } else { "0.034" };
+    let code: octal::GameCode = match rules_str.parse() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("invalid game code {:?}: {}", rules_str, e);
+            std::process::exit(1);
+        }
+    };
 
     let max_full_memory = if args.len() > 2 {
         args[2].parse::<usize>().unwrap()
@@ -62,37 +108,343 @@ fn main() {
         0
     };
 
+    let dir = Path::new(".");
+
+    // the options after the sizes, as --<name>=<value>, or --<name> alone for the switches
+    let mut width = None;
+    let mut backend = VecBin::NAME;
+    let mut levels = 1;
+    let mut threads = 1;
+    let mut speculation = 1;
+    let mut simd = false;
+    let mut mapped = false;
+    let mut packed = false;
+    let mut max_period = 1 << 20;
+    for arg in args.iter().skip(4) {
+        if let Some(x) = arg.strip_prefix("--width=") {
+            width = Some(x.parse::<u32>().unwrap());
+        } else if let Some(x) = arg.strip_prefix("--bitset=") {
+            backend = x;
+        } else if let Some(x) = arg.strip_prefix("--levels=") {
+            // levels of the sparse space, 1 for the plain split into R and C
+            levels = x.parse::<usize>().unwrap();
+        } else if let Some(x) = arg.strip_prefix("--threads=") {
+            // threads used for a single heap, 1 to compute the heaps serially
+            threads = x.parse::<usize>().unwrap();
+        } else if let Some(x) = arg.strip_prefix("--speculation=") {
+            // heaps of the front computed at once, with their proofs split between the threads,
+            // 1 to compute them one by one
+            speculation = x.parse::<usize>().unwrap();
+        } else if let Some(x) = arg.strip_prefix("--max-period=") {
+            // the longest period looked for while the nimbers are computed (in tail mode at
+            // most max_tail_memory), 0 to look for one only after the front
+            max_period = x.parse::<usize>().unwrap();
+        } else if arg == "--simd" {
+            // keep a reversed copy of the sequence and scan the pairs of heaps with SIMD, which
+            // takes twice the memory
+            simd = true;
+        } else if arg == "--mapped" {
+            // keep the front in a file mapped into memory, which can be larger than RAM and is
+            // kept after the run, so that the front can be resumed
+            mapped = true;
+        } else if arg == "--packed" {
+            // store the nimbers with as many bits as the largest of them needs, which saves
+            // memory but cannot be combined with the options keeping them as plain arrays
+            packed = true;
+        } else {
+            eprintln!(
+                "unknown option {}, expected --width=, --bitset=, --levels=, --threads=, \
+                 --speculation=, --max-period=, --simd, --mapped or --packed",
+                arg
+            );
+            std::process::exit(1);
+        }
+    }
+
+    let mapped_path = octal::mapped::mapped_path(dir, &code.to_string(), max_full_memory);
+
+    // an explicit width, the width of the checkpoint or the mapped front the run resumes from,
+    // or a guess
+    let width = if let Some(width) = width {
+        width
+    } else if mapped && mapped_path.exists() {
+        octal::mapped::width(&mapped_path).unwrap().unwrap()
+    } else if max_tail_memory != 0 {
+        match octal::checkpoint::latest_width(dir, &code).unwrap() {
+            Some(width) => width,
+            None => octal::nimber::guess_width(&code, max_full_memory),
+        }
+    } else {
+        octal::nimber::guess_width(&code, max_full_memory)
+    };
+
+    if !octal::bitset::BACKENDS.contains(&backend) {
+        eprintln!(
+            "unsupported bitset {}, expected one of {:?}",
+            backend,
+            octal::bitset::BACKENDS
+        );
+        std::process::exit(1);
+    }
+    if levels == 0 {
+        eprintln!("the sparse space needs at least one level");
+        std::process::exit(1);
+    }
+    if threads == 0 {
+        eprintln!("at least one thread is needed");
+        std::process::exit(1);
+    }
+    if speculation == 0 {
+        eprintln!("at least one heap has to be computed at once");
+        std::process::exit(1);
+    }
+    if packed && (simd || mapped) {
+        eprintln!("packed nimbers cannot be scanned with SIMD or mapped to a file");
+        std::process::exit(1);
+    }
+
+    let options = Options {
+        levels,
+        threads,
+        speculation,
+        simd,
+        mapped,
+        packed,
+        max_period,
+    };
+
+    match width {
+        8 => with_backend::<u8>(
+            backend,
+            code,
+            max_full_memory,
+            max_tail_memory,
+            options,
+            dir,
+        ),
+        16 => with_backend::<u16>(
+            backend,
+            code,
+            max_full_memory,
+            max_tail_memory,
+            options,
+            dir,
+        ),
+        32 => with_backend::<u32>(
+            backend,
+            code,
+            max_full_memory,
+            max_tail_memory,
+            options,
+            dir,
+        ),
+        64 => with_backend::<u64>(
+            backend,
+            code,
+            max_full_memory,
+            max_tail_memory,
+            options,
+            dir,
+        ),
+        128 => with_backend::<u128>(
+            backend,
+            code,
+            max_full_memory,
+            max_tail_memory,
+            options,
+            dir,
+        ),
+        _ => {
+            eprintln!(
+                "unsupported nimber width {}, expected one of {:?}",
+                width,
+                octal::nimber::WIDTHS
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+fn with_backend<N: Nimber>(
+    backend: &str,
+    code: octal::GameCode,
+    max_full_memory: usize,
+    max_tail_memory: usize,
+    options: Options,
+    dir: &Path,
+) {
+    match backend {
+        "u32" => run::<N, FixedBin<u32>>(code, max_full_memory, max_tail_memory, options, dir),
+        "u64" => run::<N, FixedBin<u64>>(code, max_full_memory, max_tail_memory, options, dir),
+        "u128" => run::<N, FixedBin<u128>>(code, max_full_memory, max_tail_memory, options, dir),
+        _ => run::<N, VecBin>(code, max_full_memory, max_tail_memory, options, dir),
+    }
+}
+
+fn run<N: Nimber, B: BitSet>(
+    code: octal::GameCode,
+    max_full_memory: usize,
+    max_tail_memory: usize,
+    options: Options,
+    dir: &Path,
+) {
     let start = Instant::now();
 
     println!(
-        "nimber bitsize {}, maxval {}",
-        octal::Nimber::BITS,
-        octal::Nimber::MAX
+        "nimber bitsize {}, maxval {}, bitset {}, levels {}, threads {}, speculation {}, simd {}, mapped {}, packed {}, max period {}",
+        N::BITS,
+        N::MAX,
+        B::NAME,
+        options.levels,
+        options.threads,
+        options.speculation,
+        // the scan has no vector version for the widest nimbers, which use the loop
+        options.simd && N::VECTOR,
+        options.mapped,
+        options.packed,
+        options.max_period
     );
 
-    let mut g = octal::Game::new(rules_str, max_full_memory, max_tail_memory);
+    let mut g = Game::<N, B>::new(code, max_full_memory, max_tail_memory);
+    g.levels = options.levels;
+    g.threads = options.threads;
+    g.speculation = options.speculation;
+    if options.simd {
+        g.nimbers.use_reversed();
+    }
+    if options.packed {
+        g.nimbers.use_packed();
+    }
+
+    let mut computed = 0;
+    if options.mapped {
+        let path = octal::mapped::mapped_path(dir, &g.code.to_string(), max_full_memory);
+        computed = match g.map_front(&path) {
+            Ok(computed) => computed,
+            Err(e) => {
+                eprintln!("unable to map the front to {:?}: {}", path, e);
+                std::process::exit(1);
+            }
+        };
+    }
+
+    // repeating codes are arithmetic periodic, and are left to `Game::check_arithmetic_period`
+    let detector = if options.max_period != 0 && g.repeating.is_empty() {
+        Some(PeriodDetector::new(&g.rules, options.max_period))
+    } else {
+        None
+    };
+
+    let checkpoint = if max_tail_memory != 0 && g.can_use_tail() {
+        octal::checkpoint::latest(dir, &g.code).unwrap()
+    } else {
+        None
+    };
+
+    if let Some(checkpoint) = checkpoint {
+        let first = checkpoint.n;
+        println!("resuming from checkpoint at {}", first);
+        if let Err(e) = g.restore(dir, checkpoint) {
+            eprintln!("unable to resume from checkpoint at {}: {}", first, e);
+            std::process::exit(1);
+        }
+        g.dump_freqs(first, &start);
+        tail(g, first, first, dir, &start, detector);
+        return;
+    }
+
     g.init();
-    for n in g.rules.len()..max_full_memory {
-        g.calc_rc(n);
-        g.occasional_info(n, &start);
+    let mut first = g.rules.len();
+    if computed > first {
+        // the nimbers have the width of the file, so they fit
+        println!("resuming from the mapped front at {}", computed);
+        g.replay(computed).unwrap();
+        first = computed;
+    }
+    front(g, first, dir, &start, detector);
+}
+
+/// Compute the front from heap `first` on, look for a period and continue in tail mode if there
+/// is none.  The run stops as soon as the detector finds a period.
+fn front<N: Nimber, B: BitSet>(
+    mut g: Game<N, B>,
+    first: usize,
+    dir: &Path,
+    start: &Instant,
+    mut detector: Option<PeriodDetector>,
+) {
+    let rules_str = &g.code.to_string();
+    let max_full_memory = g.nimbers.g.len();
+    let max_tail_memory = g.nimbers.g_back.len();
+
+    let mut n = first;
+    while n < max_full_memory {
+        match g.calc_rc_speculative(n, max_full_memory) {
+            Ok(computed) => {
+                for m in n..n + computed {
+                    g.occasional_info(m, start);
+                    if let Some((period_start, period)) = detector
+                        .as_mut()
+                        .and_then(|d| d.update(&g.nimbers.front(), m + 1))
+                    {
+                        report_period(period_start, period, m + 1);
+                        g.sync_front(m + 1).unwrap();
+                        g.dump_stats(m, start);
+                        println!("total: {:?}", start.elapsed());
+                        return;
+                    }
+                }
+                if (n + computed) / SYNC_EVERY > n / SYNC_EVERY {
+                    g.sync_front(n + computed).unwrap();
+                }
+                n += computed;
+            }
+            Err(overflow) => {
+                let n = overflow.n;
+                return front(promote(g, overflow), n, dir, start, detector);
+            }
+        }
     }
-    g.dump_freqs(max_full_memory, &start);
-    g.dump_stats(max_full_memory - 1, &start);
+    g.sync_front(max_full_memory).unwrap();
+    g.dump_freqs(max_full_memory, start);
+    g.dump_stats(max_full_memory - 1, start);
     let start_period = Instant::now();
 
-    let period_found = g.check_period(max_full_memory);
+    let period_found = if g.repeating.is_empty() {
+        g.check_period(max_full_memory)
+    } else {
+        match g.check_arithmetic_period(max_full_memory) {
+            Some((period_start, period, saltus)) => {
+                println!("period start: {}\n", period_start);
+                println!("period: {}\n", period);
+                println!("saltus: {}\n", saltus);
+                true
+            }
+            None => false,
+        }
+    };
     println!("total period: {:?}", start_period.elapsed());
     println!("total: {:?}", start.elapsed());
 
-    if !period_found && max_tail_memory != 0 {
+    if !period_found && max_tail_memory != 0 && !g.can_use_tail() {
+        println!(
+            "tail mode is not available for repeating codes and games dividing a heap into three"
+        );
+    } else if !period_found && max_tail_memory != 0 {
         let mut last = 0;
 
         let paths = glob::glob(&format!("nimbers_{rules_str}_*")).unwrap();
 
         for path in paths {
-            let s = path.unwrap().file_name().unwrap().to_str().unwrap().to_string();
+            let s = path
+                .unwrap()
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string();
             let (_, val) = s.rsplit_once("_").expect("bad name");
-            let v : usize = val.parse().unwrap();
+            let v: usize = val.parse().unwrap();
             last = v.max(last);
         }
 
@@ -100,28 +452,21 @@ fn main() {
             last = max_full_memory;
             g.nimbers.copy_to_g_back();
         } else {
-            let p = format!("nimbers_{}_{}", rules_str, last);
-            let path = Path::new(&p);
-            let loaded = load(max_tail_memory, path);
-            g.nimbers.g_back = loaded;
-        }
-
-        let nimber_bytes = (octal::Nimber::BITS / u8::BITS) as usize;
-        let mut buf: Vec<u8> = vec![0; max_tail_memory * nimber_bytes];
-
-        for n in last.. {
-            if n % max_tail_memory == 0 {
-                for i in 0..max_tail_memory {
-                    let nim = g.nimbers.g_back[i];
-                    for b in 0..nimber_bytes {
-                        let loc = (i * nimber_bytes) + (nimber_bytes - b) - 1;
-                        buf[loc] = (nim >> b * 8) as u8;
-                    }
+            // nimbers saved without a checkpoint, the statistics only cover the front
+            println!("no checkpoint found, resuming from nimbers at {}", last);
+            let path = octal::checkpoint::nimbers_path(dir, rules_str, last);
+            let first = last - max_tail_memory;
+            println!("Reading nimbers from {:?}", path);
+            match octal::chunk::read_expected(&path, rules_str, first, max_tail_memory) {
+                Ok(g_back) => g.nimbers.load_g_back(g_back),
+                Err(e) => {
+                    eprintln!("unable to resume from nimbers {:?}: {}", path, e);
+                    std::process::exit(1);
                 }
-                save(n, rules_str, &buf)
             }
-            g.calc_rc_back(n);
-            g.occasional_info_back(last, n, &start);
         }
+
+        g.save_front(dir).unwrap();
+        tail(g, last, last, dir, start, detector);
     }
 }
@@ -0,0 +1,63 @@
+use std::env;
+
+use octal::octal;
+
+use octal::misere::Misere;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let rules_str = if args.len() > 1 { &args[1] } else { "0.75" };
+    let code: octal::GameCode = match rules_str.parse() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("invalid game code {:?}: {}", rules_str, e);
+            std::process::exit(1);
+        }
+    };
+
+    let max_heap = if args.len() > 2 {
+        args[2].parse::<usize>().unwrap()
+    } else {
+        20
+    };
+
+    let max_order = if args.len() > 3 {
+        args[3].parse::<usize>().unwrap()
+    } else {
+        1000
+    };
+
+    // the largest number of heaps of the positions the candidates are read off
+    let max_depth = if args.len() > 4 {
+        args[4].parse::<usize>().unwrap()
+    } else {
+        4
+    };
+
+    println!("misère quotients of {} up to heap {}", code, max_heap);
+
+    let mut misere = Misere::new(&code, max_order, max_depth);
+    let mut last = String::new();
+    for n in 1..=max_heap {
+        match misere.calc_quotient(n) {
+            Ok(quotient) => {
+                // the quotients which change with the heap, as in the tables of Plambeck and Siegel
+                let presentation = quotient.presentation();
+                let p = quotient.p_portion(&presentation);
+                let current = format!("{}, P = {{{}}}", presentation, p.join(", "));
+                if current != last {
+                    println!("{}: order {}, {}", n, quotient.order(), current);
+                    last = current;
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    println!();
+    println!("{}", misere.quotient());
+}
@@ -1,11 +1,38 @@
 // use super::game;
 use bitvec::prelude::*;
-use cfg_if;
 use serde::{Deserialize, Serialize};
-use std::cmp::Reverse;
-use std::collections::HashSet;
+use std::path::Path;
 use std::time::Instant;
 
+pub mod bitset;
+pub mod checkpoint;
+pub mod chunk;
+pub mod code;
+pub mod history;
+pub mod mapped;
+pub mod misere;
+pub mod nimber;
+pub mod packed;
+pub mod parallel;
+pub mod period;
+pub mod repeating;
+pub mod simd;
+pub mod speculation;
+pub mod storage;
+pub mod store;
+#[cfg(test)]
+mod testing;
+
+pub use bitset::{BitSet, FixedBin, VecBin};
+pub use code::{GameCode, ParseGameCodeError};
+pub use history::History;
+pub use nimber::{Nimber, Overflow};
+use period::Search;
+use repeating::FirstOccurrences;
+pub use storage::{Storage, Values};
+pub use store::NimberStore;
+use store::{Chain, Front, Ring};
+
 /// Rule represents possible moves from a position n after removing some i tokens are removed from a heap
 ///
 /// If all is true, then 0 may be the successor of n if n == i (all tokens may be taken from the
@@ -15,240 +42,492 @@ use std::time::Instant;
 /// If divide is true, then a pair (i, n - i) may be the successor of n if n > i (some tokens may
 /// be taken from the heap, but the heap must be divided into two nonempty heaps after the tokens
 /// are taken).
-#[derive(Copy, Clone, Debug, PartialEq)]
+/// If divide3 is true, then a triple (a, b, c) with a + b + c == n - i may be the successor of n
+/// (the heap must be divided into three nonempty heaps after the tokens are taken).  This is only
+/// possible in hexadecimal games, where digits 8 to F are allowed.
+///
+/// The rule for i == 0 (the digit d0 before the decimal point) describes moves which do not remove
+/// any tokens, so only divide and divide3 are meaningful there: a heap may be divided into two
+/// (or three) nonempty heaps without taking anything.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
 pub struct Rule {
     pub all: bool,
     pub some: bool,
     pub divide: bool,
+    pub divide3: bool,
 }
 
-impl From<char> for Rule {
-    fn from(c: char) -> Self {
-        let d = c.to_digit(10).unwrap();
+impl Rule {
+    pub fn from_digit(d: u8) -> Self {
         Rule {
             all: ((d & 1) != 0),
             some: ((d & 2) != 0),
             divide: ((d & 4) != 0),
+            divide3: ((d & 8) != 0),
         }
     }
 }
 
-cfg_if::cfg_if! {
-    if #[cfg(feature = "bits_bitvec")] {
-        pub type BitV = BitVec<u64, Msb0>;
-    } else if #[cfg(feature = "bits_u32")] {
-        pub type BitV = u32;
-    } else if #[cfg(feature = "bits_u64")] {
-        pub type BitV = u64;
-    } else if #[cfg(feature = "bits_u128")] {
-        pub type BitV = u128;
-    } else {
-        pub type BitV = BitVec<u64, Msb0>;
+impl From<char> for Rule {
+    fn from(c: char) -> Self {
+        Rule::from_digit(c.to_digit(16).unwrap() as u8)
     }
 }
 
-#[derive(Clone)]
-pub struct Bin {
-    bits: BitV,
+/// Transform a game string like "0.034" (or a hexadecimal one, like "0.B4") into a Vector of Rules
+///
+/// I-th element of the vector is a Rule which represents possible moves after removing i tokens
+/// from a heap.
+pub fn rules_from_str(game: &str) -> Vec<Rule> {
+    game.chars().filter(|&x| x != '.').map(Rule::from).collect()
 }
 
-#[cfg(any(
-    feature = "bits_bitvec",
-    not(any(feature = "bits_u32", feature = "bits_u64", feature = "bits_u128"))
-))]
-impl Bin {
-    fn set_bit(&mut self, x: usize) {
-        self.bits.set(x, true);
+/// The rule for removing i tokens from a heap in the game with the given rules, followed by the
+/// repeating ones (empty for finite codes).
+pub fn rule_at(rules: &[Rule], repeating: &[Rule], i: usize) -> Rule {
+    if i < rules.len() {
+        rules[i]
+    } else if repeating.is_empty() {
+        Rule::default()
+    } else {
+        repeating[(i - rules.len()) % repeating.len()]
     }
+}
 
-    fn zero_bits(&mut self) {
-        self.bits.set_elements(0);
+/// The largest number of heaps a single move may leave behind (at least two, as in octal games).
+pub fn max_heaps(rules: &[Rule]) -> usize {
+    if rules.iter().any(|r| r.divide3) {
+        3
+    } else {
+        2
     }
+}
 
-    fn get(&self, x: usize) -> bool {
-        self.bits[x]
-    }
+/// The smallest number of computed nimbers g[0..n] which confirms that the game is periodic with
+/// the given preperiod `start` and `period`.
+///
+/// For octal games g(n + period) == g(n) must hold for all start <= n < 2 * start + period + t,
+/// where t is the largest number of tokens that may be removed in a single move.  In hexadecimal
+/// games a heap may be divided into three heaps, and the largest of them is only guaranteed to
+/// have at least a third of the tokens, so the range grows to 3 * start + 2 * period + t.
+pub fn periodicity_bound(rules: &[Rule], start: usize, period: usize) -> usize {
+    let heaps = max_heaps(rules);
+    heaps * start + heaps * period + rules.len() - 1
+}
 
-    fn lowest_unset(&self) -> usize {
-        self.bits.first_zero().unwrap()
+/// Call `f` with the nim-value of every division of a heap of size m into three nonempty heaps
+/// a <= b <= c.
+pub(crate) fn for_each_triple<N: Nimber>(
+    g: &(impl Values<N> + ?Sized),
+    m: usize,
+    mut f: impl FnMut(usize),
+) {
+    for a in 1..=m / 3 {
+        for b in a..=(m - a) / 2 {
+            f((g.get(a) ^ g.get(b) ^ g.get(m - a - b)).to_usize());
+        }
     }
+}
 
-    fn make(largest: Nimber) -> Self {
-        let bs = 2 * (largest as usize).next_power_of_two() + 2;
-        Self {
-            bits: bitvec!(u64, Msb0; 0; bs),
+/// Evaluate $body with $front bound to the front of $nimbers, read from its slice unless the
+/// nimbers are packed, so that the loops over the pairs of heaps index the slice directly.
+macro_rules! with_front {
+    ($nimbers:expr, |$front:ident| $body:expr) => {
+        match $nimbers.g.as_slice() {
+            Some(g) => {
+                let $front = Front::new(g, &$nimbers.g_rev);
+                $body
+            }
+            None => {
+                let $front = $nimbers.front();
+                $body
+            }
+        }
+    };
+}
+
+/// Evaluate $body with $tail bound to the heaps in memory while computing the heap $n in tail
+/// mode (`Nimbers::tail`), read from the slices of the front and the ring when both have one.
+macro_rules! with_tail {
+    ($nimbers:expr, $n:expr, |$tail:ident| $body:expr) => {
+        match ($nimbers.g.as_slice(), $nimbers.g_back.as_slice()) {
+            (Some(g), Some(g_back)) => {
+                let $tail = Chain(
+                    Front::new(g, &$nimbers.g_rev),
+                    Ring::new(g_back, &$nimbers.g_back_rev, $n),
+                );
+                $body
+            }
+            _ => {
+                let $tail = $nimbers.tail($n);
+                $body
+            }
+        }
+    };
+}
+
+/// Set the values of the moves taking some tokens from a heap of size n.
+fn set_seen_bits_from_some_moves<N: Nimber, B: BitSet>(
+    rules: &[Rule],
+    store: &impl NimberStore<N>,
+    n: usize,
+    seen: &mut B,
+) {
+    for (i, rule) in rules.iter().enumerate().skip(1) {
+        if rule.some {
+            seen.set_bit(store.get(n - i).to_usize());
         }
     }
+}
 
-    fn count_unset(&self) -> usize {
-        self.bits.count_zeros()
+/// Set the values x ^ y of the divisions of a heap of size n into two, in which x is the value
+/// of one of the heaps of `rare` (sorted by size), splitting them between threads if there are
+/// at least parallel_heaps of them.
+fn set_seen_bits_from_rare<N: Nimber, B: BitSet>(
+    rules: &[Rule],
+    rare: &[(usize, N)],
+    store: &(impl NimberStore<N> + Sync),
+    n: usize,
+    threads: usize,
+    parallel_heaps: usize,
+    seen: &mut B,
+) {
+    if threads > 1 && rare.len() >= parallel_heaps {
+        parallel::set_seen_bits_from_rare_parallel(rules, rare, store, n, threads, seen);
+        return;
     }
 
-    fn find_first_unset_also_unset_in(&self, other: &Self) -> usize {
-        for i in 0..other.bits.len() {
-            if !self.get(i) && !other.get(i) {
-                return i;
+    for (i, rule) in rules.iter().enumerate() {
+        if rule.divide {
+            // the heaps are sorted, so the rest are too large as well
+            let fit = rare.partition_point(|&(idx, _)| n > i + idx);
+            for &(idx, x) in &rare[..fit] {
+                seen.set_bit((x ^ store.get(n - i - idx)).to_usize());
             }
         }
+    }
+}
 
-        self.bits.len() - 1
+/// Look for the values smaller than first_common missing in mex among the values of the pairs
+/// of heaps (j, m - j) for j in `pairs`, setting them in mex, and return the j which found the
+/// last of them, if all were found.
+///
+/// Runs of the pairs the store has as slices are scanned with `Scan`.
+fn prove_pairs<N: Nimber, B: BitSet>(
+    store: &impl NimberStore<N>,
+    m: usize,
+    pairs: std::ops::Range<usize>,
+    first_common: usize,
+    mex: &mut B,
+    remaining_unset: &mut usize,
+) -> Option<usize> {
+    let mut j = pairs.start;
+    while j < pairs.end {
+        let (Some(a), Some(b)) = (store.forward(j), store.backward(m - j)) else {
+            break;
+        };
+        let len = std::cmp::min(std::cmp::min(a.len(), b.len()), pairs.end - j);
+        if let Some(k) = scan_missing(&a[..len], &b[..len], first_common, mex, remaining_unset) {
+            return Some(j + k);
+        }
+        j += len;
     }
 
-    fn copy_up_to_inclusive(&self, x: usize) -> Self {
-        Self {
-            bits: self.bits[0..x].to_owned(),
+    for j in j..pairs.end {
+        let loc = (store.get(j) ^ store.get(m - j)).to_usize();
+
+        if loc < first_common && !mex.get(loc) {
+            // a rare value smaller than first_common and not previously observed found
+            mex.set_bit(loc);
+            *remaining_unset -= 1;
+            if *remaining_unset == 0 {
+                // all smaller values than first_common found, the value is the smallest not
+                // observed common
+                return Some(j);
+            }
         }
     }
+
+    None
+}
+
+pub struct Nimbers<N: Nimber> {
+    pub g: Storage<N>,
+    pub g_back: Storage<N>,
+    /// g reversed (g_rev[g.len() - 1 - m] == g[m]), kept only after `use_reversed`, so that the
+    /// heaps j and m - j of the pairs (j, m - j) are both read forwards as j grows.
+    pub g_rev: Vec<N>,
+    /// g_back reversed in the same way.
+    pub g_back_rev: Vec<N>,
+    pub rare: Vec<(usize, N)>,
+    /// Heaps with a value which stops being common at each level of the sparse space after the
+    /// first (the first one is `rare`).
+    pub levels: Vec<Vec<(usize, N)>>,
+    pub first: FirstOccurrences,
 }
 
-#[cfg(all(
-    not(feature = "bits_bitvec"),
-    any(feature = "bits_u32", feature = "bits_u64", feature = "bits_u128")
-))]
-impl Bin {
-    fn set_bit(&mut self, x: usize) {
-        self.bits |= 1 << x;
+impl<N: Nimber> Nimbers<N> {
+    /// `classes` is the length of the repeating part of the game code (0 for finite codes).
+    pub fn new(max_full_memory: usize, max_tail_memory: usize, classes: usize) -> Self {
+        Self {
+            g: Storage::Memory(vec![N::MAX; max_full_memory]),
+            g_back: Storage::Memory(vec![N::MAX; max_tail_memory]),
+            g_rev: vec![],
+            g_back_rev: vec![],
+            rare: vec![],
+            levels: vec![],
+            first: FirstOccurrences::new(classes),
+        }
     }
 
-    fn zero_bits(&mut self) {
-        self.bits = 0;
+    /// The front as a `NimberStore`.
+    pub fn front(&self) -> Front<'_, N> {
+        Front::new(&self.g, &self.g_rev)
     }
 
-    fn get(&self, x: usize) -> bool {
-        self.bits & 1 << x != 0
+    /// The heaps in memory while computing the heap n in tail mode: the front and the ring.
+    pub fn tail(&self, n: usize) -> Chain<Front<'_, N>, Ring<'_, N>> {
+        Chain(self.front(), Ring::new(&self.g_back, &self.g_back_rev, n))
     }
 
-    fn lowest_unset(&self) -> usize {
-        self.bits.trailing_ones() as usize
+    pub fn last(&self, n: usize) -> N {
+        self.g_back.get(n % self.g_back.len())
     }
 
-    fn make(largest: Nimber) -> Self {
-        let bs = (largest as u32 + 2).next_power_of_two() + 2;
-        println!("{} largest, {} bs {}", largest, bs, BitV::BITS);
-        // assert!(bs < BitV::BITS);
-        Self {
-            bits: if bs > BitV::BITS {
-                0
-            } else {
-                !(0 as BitV) << (bs)
-            },
+    pub fn copy_to_g_back(&mut self) {
+        let max_tail_memory = self.g_back.len();
+        let max_full_memory = self.g.len();
+        let tail = (max_full_memory - max_tail_memory..max_full_memory).map(|m| self.g.get(m));
+        self.load_g_back(tail.collect());
+    }
+
+    /// Keep the reversed copies of g and g_back from now on, for `Scan`, if it has a vector
+    /// version for N (see `Scan::VECTOR`).
+    ///
+    /// The scan reads the front as a slice, so it is not used with packed nimbers.
+    pub fn use_reversed(&mut self) {
+        if !N::VECTOR {
+            return;
         }
+        self.g_rev = self.g.iter().rev().collect();
+        self.g_back_rev = self.g_back.iter().rev().collect();
     }
 
-    fn count_unset(&self) -> usize {
-        self.bits.count_zeros() as usize
+    /// Keep g and g_back bit-packed from now on, see `Storage::pack`.
+    pub fn use_packed(&mut self) {
+        self.g.pack();
+        self.g_back.pack();
     }
 
-    fn find_first_unset_also_unset_in(&self, other: &Self) -> usize {
-        Self {
-            bits: (self.bits | other.bits),
+    pub fn reversed(&self) -> bool {
+        !self.g_rev.is_empty()
+    }
+
+    /// The front as a slice for `Scan`, if the reversed copies are kept and it is not packed.
+    fn scanned(&self) -> Option<&[N]> {
+        if self.reversed() {
+            self.g.as_slice()
+        } else {
+            None
         }
-        .lowest_unset()
     }
 
-    fn copy_up_to_inclusive(&self, _x: usize) -> Self {
-        // self.clone()
-        Self {
-            bits: ((!0) << _x) | self.bits,
-        } // maybe set upper bits to 1?
+    /// Set the value of heap m of the front.
+    pub fn set(&mut self, m: usize, x: N) {
+        self.g.set(m, x);
+        if self.reversed() {
+            let len = self.g_rev.len();
+            self.g_rev[len - 1 - m] = x;
+        }
     }
-}
 
-/// Transform a game string like "0.034" into a Vector of Rules
-///
-/// I-th element of the vector is a Rule which represents possible moves after removing i tokens
-/// from a heap.
-pub fn rules_from_str(game: &str) -> Vec<Rule> {
-    game.chars()
-        .filter(|&x| x != '.')
-        .map(|c| Rule::from(c))
-        .collect()
-}
+    /// Set the value of heap m in the tail ring.
+    pub fn set_last(&mut self, m: usize, x: N) {
+        let loc = m % self.g_back.len();
+        self.g_back.set(loc, x);
+        if self.reversed() {
+            let len = self.g_back_rev.len();
+            self.g_back_rev[len - 1 - loc] = x;
+        }
+    }
 
-cfg_if::cfg_if! {
-    if #[cfg(feature = "nimber_u8")] {
-        pub type Nimber = u8;
-    } else if #[cfg(feature = "nimber_u16")] {
-        pub type Nimber = u16;
-    } else if #[cfg(feature = "nimber_u32")] {
-        pub type Nimber = u32;
-    } else {
-        pub type Nimber = u16;
+    /// Replace the whole front, keeping it where it is.
+    pub fn load_g(&mut self, g: Vec<N>) {
+        self.g.load(&g);
+        if self.reversed() {
+            self.g_rev = g.into_iter().rev().collect();
+        }
     }
-}
 
-pub struct Nimbers {
-    pub g: Vec<Nimber>,
-    pub g_back: Vec<Nimber>,
-    pub rare: Vec<(usize, Nimber)>,
-}
+    /// Keep the front in another place, which already holds its nimbers.
+    pub fn set_front(&mut self, g: Storage<N>) {
+        self.g = g;
+        if self.reversed() {
+            self.g_rev = self.g.iter().rev().collect();
+        }
+    }
 
-impl Nimbers {
-    pub fn new(max_full_memory: usize, max_tail_memory: usize) -> Self {
-        Self {
-            g: vec![Nimber::max_value(); max_full_memory],
-            g_back: vec![Nimber::max_value(); max_tail_memory],
-            rare: vec![],
+    /// Replace the whole tail ring, keeping it where it is.
+    pub fn load_g_back(&mut self, g_back: Vec<N>) {
+        self.g_back.load(&g_back);
+        if self.reversed() {
+            self.g_back_rev = g_back.into_iter().rev().collect();
         }
     }
 
-    pub fn last(&self, n: usize) -> Nimber {
-        self.g_back[n % self.g_back.len()]
+    /// Convert the nimbers to a wider type.
+    pub fn widen<M: Nimber>(self) -> Nimbers<M> {
+        let widen = |x: N| M::from_usize(x.to_usize());
+        Nimbers {
+            g: self.g.widen(),
+            g_back: self.g_back.widen(),
+            // the wider nimbers are scanned only if they have a vector version as well
+            g_rev: self
+                .g_rev
+                .into_iter()
+                .filter(|_| M::VECTOR)
+                .map(widen)
+                .collect(),
+            g_back_rev: self
+                .g_back_rev
+                .into_iter()
+                .filter(|_| M::VECTOR)
+                .map(widen)
+                .collect(),
+            rare: self.rare.into_iter().map(|(i, x)| (i, widen(x))).collect(),
+            levels: self
+                .levels
+                .into_iter()
+                .map(|heaps| heaps.into_iter().map(|(i, x)| (i, widen(x))).collect())
+                .collect(),
+            first: self.first,
+        }
     }
 
-    pub fn copy_to_g_back(&mut self) {
-        let max_tail_memory = self.g_back.len();
-        let max_full_memory = self.g.len();
-        self.g_back = self.g[(max_full_memory - max_tail_memory)..max_full_memory].into();
+    /// Heaps with a value which stops being common at the given level of the sparse space.
+    pub fn rare_at(&self, level: usize) -> &[(usize, N)] {
+        if level == 0 {
+            &self.rare
+        } else {
+            &self.levels[level - 1]
+        }
+    }
+
+    fn rare_at_mut(&mut self, level: usize) -> &mut Vec<(usize, N)> {
+        if level == 0 {
+            &mut self.rare
+        } else {
+            &mut self.levels[level - 1]
+        }
     }
 }
 
-pub struct Stats {
-    pub largest_nimber: Nimber,
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+// the bounds on N are implied by Nimber
+#[serde(bound = "")]
+pub struct Stats<N: Nimber> {
+    pub largest_nimber: N,
     pub frequencies: Vec<usize>,
     pub largest_nimber_index: usize,
     pub prev_values: usize,
-    pub latest_rare: Nimber,
+    pub latest_rare: N,
     pub latest_rare_index: usize,
+    /// Number of successor values first found by pairing the heaps of each level of the sparse
+    /// space with the others, summed over all heaps.
+    #[serde(default)]
+    pub eliminated: Vec<usize>,
 }
 
-pub struct Bits {
-    pub rare: Bin,
-    pub seen: Bin,
+/// A level of the sparse space.
+///
+/// The values x with popcount(x & mask) mod 2 == parity at this and all the previous levels are
+/// the common ones.  At the first level these are C, the complement of the kernel R of the
+/// functional, and every next level splits the common values of the previous one into a more
+/// and a less frequent coset of a subgroup of twice the index.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Level {
+    pub mask: usize,
+    pub parity: bool,
 }
 
-// pub fn make_bitset(largest: Nimber) -> BitV {
+impl Level {
+    pub fn is_common(&self, x: usize) -> bool {
+        ((x & self.mask).count_ones() % 2 == 1) == self.parity
+    }
+}
+
+pub struct Bits<B: BitSet> {
+    /// The values in the kernel of all the levels, which are the only values left to `prove`.
+    pub rare: B,
+    pub seen: B,
+    /// The levels of the sparse space, from the first one.
+    pub chain: Vec<Level>,
+}
+
+// pub fn make_bitset(largest: usize) -> BitV {
 //     let bits = 2 * (largest as usize).next_power_of_two() + 2;
 //     bitvec!(u64, Msb0; 0; bits)
 // }
 
-impl Bits {
+impl<B: BitSet> Default for Bits<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: BitSet> Bits<B> {
     pub fn new() -> Self {
         Self {
-            rare: Bin::make(0),
-            seen: Bin::make(0),
+            rare: B::make(0),
+            seen: B::make(0),
+            chain: vec![],
         }
     }
 
-    pub fn resize(&mut self, largest_nimber: Nimber) {
-        self.rare = Bin::make(largest_nimber);
-        self.seen = Bin::make(largest_nimber);
+    /// The level at which x stops being common, None if it is common at all of them.
+    pub fn level(&self, x: usize) -> Option<usize> {
+        self.chain.iter().position(|level| !level.is_common(x))
+    }
+
+    pub fn resize(&mut self, largest_nimber: usize) {
+        self.rare = B::make(largest_nimber);
+        self.seen = B::make(largest_nimber);
+    }
+}
+
+impl<N: Nimber> Default for Stats<N> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl Stats {
+impl<N: Nimber> Stats<N> {
     pub fn new() -> Self {
         Self {
-            largest_nimber: Nimber::min_value(),
+            largest_nimber: N::MIN,
             frequencies: vec![],
             largest_nimber_index: 0,
             prev_values: 0,
-            latest_rare: Nimber::min_value(),
+            latest_rare: N::MIN,
             latest_rare_index: 0,
+            eliminated: vec![],
         }
     }
 
-    pub fn initialize(&mut self, front: &Vec<Nimber>, first_uninitialized: usize) {
+    /// Convert the statistics to a wider type.
+    pub fn widen<M: Nimber>(self) -> Stats<M> {
+        Stats {
+            largest_nimber: M::from_usize(self.largest_nimber.to_usize()),
+            frequencies: self.frequencies,
+            largest_nimber_index: self.largest_nimber_index,
+            prev_values: self.prev_values,
+            latest_rare: M::from_usize(self.latest_rare.to_usize()),
+            latest_rare_index: self.latest_rare_index,
+            eliminated: self.eliminated,
+        }
+    }
+
+    pub fn initialize(&mut self, front: &[N], first_uninitialized: usize) {
         self.set_largest_nimber(front, first_uninitialized);
         self.resize_frequencies();
         self.initialize_frequencies(front, first_uninitialized);
@@ -256,15 +535,18 @@ impl Stats {
 
     pub fn resize_frequencies(&mut self) {
         self.frequencies
-            .resize((self.largest_nimber as usize + 2).next_power_of_two(), 0);
+            .resize((self.largest_nimber.to_usize() + 2).next_power_of_two(), 0);
     }
-    pub fn set_largest_nimber(&mut self, g: &Vec<Nimber>, first_uninitialized: usize) {
-        self.largest_nimber = *g[1..first_uninitialized].iter().max().unwrap_or(&2);
+    pub fn set_largest_nimber(&mut self, g: &[N], first_uninitialized: usize) {
+        self.largest_nimber = *g[1..first_uninitialized]
+            .iter()
+            .max()
+            .unwrap_or(&N::from_usize(2));
     }
 
-    pub fn initialize_frequencies(&mut self, g: &Vec<Nimber>, first_uninitialized: usize) {
-        for n in 0..first_uninitialized {
-            self.frequencies[g[n] as usize] += 1;
+    pub fn initialize_frequencies(&mut self, g: &[N], first_uninitialized: usize) {
+        for &nim in &g[0..first_uninitialized] {
+            self.frequencies[nim.to_usize()] += 1;
         }
     }
 
@@ -275,67 +557,161 @@ impl Stats {
     /// * for all set bit x, y in rares  x ^ y is also set,
     /// * for all unset bits x, y in rares, x ^ y is set,
     /// * for all set bits x and unset bits y in C, x ^ y in unset.
+    ///
     /// while at the same time maximizing the sum of freq[x] if rares[x] is unset.
-    pub fn gen_rares(&self) -> Bin {
-        let mut r = HashSet::new();
-        let mut c = HashSet::new();
-        let mut vals: Vec<(usize, usize)> =
-            self.frequencies.iter().map(|&e| e).enumerate().collect();
-        vals.sort_by_key(|(_, f)| Reverse(*f));
-
-        r.insert(0);
-        for (x, _) in vals {
-            if r.contains(&x) || c.contains(&x) {
-                continue;
-            } else {
-                c.insert(x);
-                let mut inserted = true;
-                while inserted {
-                    inserted = false;
-                    for &c1 in c.iter() {
-                        for &c2 in c.iter() {
-                            inserted |= r.insert(c1 ^ c2);
-                        }
-                    }
+    ///
+    /// Such R is a subgroup of index 2 of the values with xor, treated as a vector space over
+    /// GF(2), so it is the kernel of a linear functional x -> popcount(x & mask) mod 2, chosen by
+    /// `rare_mask`.  With more levels in the chain, R is the kernel of all their functionals, a
+    /// subgroup of index 2^levels.
+    pub fn gen_rares<B: BitSet>(&self, chain: &[Level]) -> B {
+        let mut rares = B::make(self.largest_nimber.to_usize());
+        for x in 0..self.frequencies.len() {
+            if chain
+                .iter()
+                .all(|level| (x & level.mask).count_ones() % 2 == 0)
+            {
+                rares.set_bit(x);
+            }
+        }
+        rares
+    }
 
-                    let mut new_r = r.to_owned();
+    /// The mask of the functional whose kernel has the smallest sum of frequencies (the smallest
+    /// mask of those, to keep R the same for the same frequencies).
+    ///
+    /// The Walsh-Hadamard transform of the frequencies gives, for all masks at once, the sum of
+    /// frequencies in the kernel minus the sum of the others, in O(k 2^k) for 2^k values.
+    pub fn rare_mask(&self) -> usize {
+        let w = walsh_hadamard(&self.frequencies);
 
-                    for &r1 in r.iter() {
-                        for &r2 in r.iter() {
-                            if r1 != 0 && r2 != 0 && r1 != r2 {
-                                inserted |= new_r.insert(r1 ^ r2);
-                            }
-                        }
+        // mask 0 would make all the values rare
+        (1..w.len()).min_by_key(|&mask| w[mask]).unwrap_or(1)
+    }
+
+    /// Choose up to `levels` levels of the sparse space, the first one with `rare_mask`.
+    ///
+    /// Each next level splits the common values of the previous one with the functional
+    /// (independent of the previous ones) which leaves the largest sum of frequencies in one of
+    /// the halves, and that half becomes the common one.  There are no more levels than
+    /// independent functionals.
+    pub fn gen_chain(&self, levels: usize) -> Vec<Level> {
+        let mut chain = vec![Level {
+            mask: self.rare_mask(),
+            parity: true,
+        }];
+        // the masks of all the combinations of the functionals chosen so far
+        let mut span = vec![0, chain[0].mask];
+
+        while chain.len() < levels && span.len() < self.frequencies.len() {
+            let common: Vec<usize> = self
+                .frequencies
+                .iter()
+                .enumerate()
+                .map(|(x, &f)| {
+                    if chain.iter().all(|level| level.is_common(x)) {
+                        f
+                    } else {
+                        0
                     }
+                })
+                .collect();
+            let w = walsh_hadamard(&common);
+
+            let mask = (1..w.len())
+                .filter(|mask| !span.contains(mask))
+                .min_by_key(|&mask| -w[mask].abs())
+                .unwrap();
+            // the common values with an odd popcount(x & mask) are the more frequent ones if
+            // w[mask] is negative
+            chain.push(Level {
+                mask,
+                parity: w[mask] < 0,
+            });
+            span = span.iter().flat_map(|&m| [m, m ^ mask]).collect();
+        }
 
-                    r = new_r;
+        chain
+    }
+}
 
-                    let mut new_c = c.to_owned();
+/// Look for the values smaller than first_common missing in mex among a[k] ^ b[k] with `Scan`,
+/// setting them in mex, and return the k which found the last of them, if all were found.
+fn scan_missing<N: Nimber, B: BitSet>(
+    a: &[N],
+    b: &[N],
+    first_common: usize,
+    mex: &mut B,
+    remaining_unset: &mut usize,
+) -> Option<usize> {
+    let mut wanted: Vec<N> = (0..first_common)
+        .filter(|&w| !mex.get(w))
+        .map(N::from_usize)
+        .collect();
+
+    let mut k = 0;
+    while let Some(hit) = N::first_xor_in(&a[k..], &b[k..], &wanted) {
+        let x = a[k + hit] ^ b[k + hit];
+        wanted.retain(|&w| w != x);
+        mex.set_bit(x.to_usize());
+        *remaining_unset -= 1;
+        if *remaining_unset == 0 {
+            return Some(k + hit);
+        }
+        k += hit + 1;
+    }
 
-                    for &r1 in r.iter() {
-                        for &c1 in c.iter() {
-                            inserted |= new_c.insert(r1 ^ c1);
-                        }
-                    }
+    None
+}
 
-                    c = new_c;
-                }
+/// The Walsh-Hadamard transform of the frequencies of 2^k values: for every mask, the sum of
+/// frequencies of x with an even popcount(x & mask) minus the sum of the others.
+fn walsh_hadamard(frequencies: &[usize]) -> Vec<i64> {
+    let mut w: Vec<i64> = frequencies.iter().map(|&f| f as i64).collect();
+    let mut h = 1;
+    while h < w.len() {
+        for i in (0..w.len()).step_by(2 * h) {
+            for j in i..i + h {
+                let (a, b) = (w[j], w[j + h]);
+                w[j] = a + b;
+                w[j + h] = a - b;
             }
         }
-
-        let mut rares = Bin::make(self.largest_nimber);
-        for &x in r.iter() {
-            rares.set_bit(x)
-        }
-        rares
+        h *= 2;
     }
+    w
 }
 
-pub struct Game {
+/// The value of a heap found by `prove`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Proof {
+    /// The candidate, with the number of pairs needed to find all the values smaller than it.
+    Common(usize, usize),
+    /// A value smaller than the candidate, missing among all the successors.
+    Rare(usize),
+}
+
+/// A game computed with nimbers of type N and bitsets of type B.
+pub struct Game<N: Nimber, B: BitSet = VecBin> {
+    pub code: GameCode,
     pub rules: Vec<Rule>,
-    pub nimbers: Nimbers,
-    pub stats: Stats,
-    pub bits: Bits,
+    /// Rules for removing rules.len() + r (mod repeating.len()) tokens, empty for finite codes.
+    pub repeating: Vec<Rule>,
+    pub nimbers: Nimbers<N>,
+    pub stats: Stats<N>,
+    pub bits: Bits<B>,
+    /// The number of levels of the sparse space, 1 for the plain split into R and C.
+    pub levels: usize,
+    /// The number of threads `rc` may use for a single heap, 1 to compute it serially.
+    pub threads: usize,
+    /// When the work on a single heap is split between the threads.
+    pub thresholds: parallel::Thresholds,
+    /// The number of heaps `calc_rc_speculative` computes at once, 1 for one by one.
+    pub speculation: usize,
+    /// The nimbers which are no longer in memory in tail mode, if they were saved.
+    pub history: Option<History<N>>,
+    /// Print the progress of the computation, like each new largest value.
+    pub verbose: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -345,73 +721,125 @@ struct Freq {
     rare: bool,
 }
 
-impl Game {
-    pub fn new(rules_str: &str, max_full_memory: usize, max_tail_memory: usize) -> Self {
+impl<N: Nimber, B: BitSet> Game<N, B> {
+    pub fn new(code: GameCode, max_full_memory: usize, max_tail_memory: usize) -> Self {
+        let repeating = code.repeating_rules();
         Game {
-            rules: rules_from_str(rules_str),
-            nimbers: Nimbers::new(max_full_memory, max_tail_memory),
+            rules: code.rules(),
+            code,
+            nimbers: Nimbers::new(max_full_memory, max_tail_memory, repeating.len()),
+            repeating,
             stats: Stats::new(),
             bits: Bits::new(),
+            levels: 1,
+            threads: 1,
+            thresholds: parallel::Thresholds::default(),
+            speculation: 1,
+            history: None,
+            verbose: true,
+        }
+    }
+
+    /// Convert the game to a wider nimber type, to continue computing it after an `Overflow`.
+    pub fn widen<M: Nimber>(self) -> Game<M, B> {
+        Game {
+            code: self.code,
+            rules: self.rules,
+            repeating: self.repeating,
+            nimbers: self.nimbers.widen(),
+            stats: self.stats.widen(),
+            bits: self.bits,
+            levels: self.levels,
+            threads: self.threads,
+            thresholds: self.thresholds,
+            speculation: self.speculation,
+            history: self.history.map(History::widen),
+            verbose: self.verbose,
         }
     }
 
+    /// Read the values of the heaps which are no longer in memory in tail mode from the rings
+    /// saved in dir by `save_checkpoint`.
+    pub fn use_history(&mut self, dir: &Path) {
+        self.history = Some(History::new(
+            dir.to_path_buf(),
+            self.code.to_string(),
+            self.nimbers.g_back.len(),
+        ));
+    }
+
+    /// The heaps before n, from the front, the tail ring and the history.
+    pub fn sequence(&self, n: usize) -> impl NimberStore<N> + '_ {
+        Chain(self.nimbers.tail(n), self.history.as_ref())
+    }
+
+    /// The rule for removing i tokens from a heap.
+    pub fn rule(&self, i: usize) -> Rule {
+        rule_at(&self.rules, &self.repeating, i)
+    }
+
+    /// Tail mode only keeps the front and the last nimbers of the sequence, so it is unable to
+    /// compute games which need values from the middle of it.
+    pub fn can_use_tail(&self) -> bool {
+        self.repeating.is_empty() && max_heaps(&self.rules) == 2
+    }
+
     /// Initialize first `rules.len()` elements of g with nim-values of positions.
     ///
     /// Calculate the first `rules.len()` elements naively, but while checking if the rule may be
     /// applied (for n in 0..rules.len(), check if i > n).
     /// This check is unnecessary for n's larger than `rules.len()`.
     pub fn initialize(&mut self) {
-        self.nimbers.g[0] = 0;
+        self.nimbers.set(0, N::from_usize(0));
 
         for n in 1..self.rules.len() {
-            let mut seen = bitvec!(u64, Msb0; 0; 2 * self.rules.len() + 2);
+            // xor of any previous nimbers is smaller than the next power of two of the largest one
+            let largest = (0..n)
+                .map(|m| self.nimbers.g.get(m))
+                .max()
+                .unwrap()
+                .to_usize();
+            let mut seen = bitvec!(u64, Msb0; 0; 2 * (largest + 1).next_power_of_two() + 2);
 
             if n < self.rules.len() && self.rules[n].all {
                 seen.set(0, true);
             }
 
-            for i in 1..self.rules.len() {
-                if self.rules[i].some && n > i {
-                    seen.set(self.nimbers.g[n - i] as usize, true);
+            for i in 0..self.rules.len() {
+                if i > 0 && self.rules[i].some && n > i {
+                    seen.set(self.nimbers.g.get(n - i).to_usize(), true);
                 }
 
                 if self.rules[i].divide && n > i {
                     for j in 1..=(n - i) / 2 {
-                        let x = self.nimbers.g[j];
-                        let y = self.nimbers.g[n - i - j];
-                        seen.set((x ^ y) as usize, true);
+                        let x = self.nimbers.g.get(j);
+                        let y = self.nimbers.g.get(n - i - j);
+                        seen.set((x ^ y).to_usize(), true);
                     }
                 }
+
+                if self.rules[i].divide3 && n > i {
+                    for_each_triple(&self.nimbers.g, n - i, |x| seen.set(x, true));
+                }
             }
 
-            self.nimbers.g[n as usize] = seen.first_zero().unwrap() as Nimber;
+            self.nimbers
+                .set(n, N::from_usize(seen.first_zero().unwrap()));
         }
     }
 
     pub fn init(&mut self) {
         self.initialize();
         let first_uninitialized = self.rules.len();
-
-        self.stats.initialize(&self.nimbers.g, first_uninitialized);
-        self.resize(first_uninitialized - 1);
-    }
-
-    pub fn set_seen_bits_from_some_moves(&mut self, n: usize) {
-        // set the non-xor values
-        for i in 1..self.rules.len() {
-            if self.rules[i].some {
-                self.bits.seen.set_bit(self.nimbers.g[n - i] as usize);
-            }
+        for n in 1..first_uninitialized {
+            self.nimbers
+                .first
+                .record(n, self.nimbers.g.get(n).to_usize());
         }
-    }
 
-    pub fn set_seen_bits_from_some_moves_back(&mut self, n: usize) {
-        // set the non-xor values
-        for i in 1..self.rules.len() {
-            if self.rules[i].some {
-                self.bits.seen.set_bit(self.nimbers.last(n - i) as usize);
-            }
-        }
+        let initialized: Vec<N> = self.nimbers.g.iter().take(first_uninitialized).collect();
+        self.stats.initialize(&initialized, first_uninitialized);
+        self.resize(first_uninitialized - 1);
     }
 
     /// Compute the nimber g[n] leveraging the sparce space phenonmenon, under the following
@@ -419,19 +847,21 @@ impl Game {
     /// * self.nimbers.g[0..n] were computed correctly, accodring to the rules of some octal game,
     /// * n is at least rules.len(),
     /// * self.bits.rare is a binary vector which in which a set bit at position i signifies that i
-    /// is a member of R
+    ///   is a member of R
     /// * the self.nimbers.rare vector represents a correct decmposition into R and C sets (that is
-    /// values from g are decomposed into two mutually exclusive sets R and C such that for all x,
-    /// y in R. x ^ y in R, and for all x, y in C, x ^ y in R, and for all x in R, y in C, x ^ y in
-    /// C).  * rare_idx_and_nimber is a vector in which contains all pairs (index, nimber) for
-    /// nimbers from g such that rares[nimber] is true (in python terms: rare_idx_and_nimber =
-    /// [(index,nimber) for nimber in g if rares[nimber]]).
+    ///   values from g are decomposed into two mutually exclusive sets R and C such that for all x,
+    ///   y in R. x ^ y in R, and for all x, y in C, x ^ y in R, and for all x in R, y in C, x ^ y in
+    ///   C).
+    /// * rare_idx_and_nimber is a vector in which contains all pairs (index, nimber) for
+    ///   nimbers from g such that rares[nimber] is true (in python terms: rare_idx_and_nimber =
+    ///   [(index,nimber) for nimber in g if rares[nimber]]).
     ///
     /// The sparse space phenomenon is an observeable phenomenon in at least some octal games, where
     /// the set of nimbers is divisible into two sets: the common (C) and the rare (R) sets, such that:
     /// * for all x, y in R. x ^ y in R,
     /// * for all x, y in C, x ^ y in R,
     /// * for all x in R, y in C, x ^ y in C.
+    ///
     /// Since most successsors of a position are in the form x ^ y where both x and y are common, then
     /// a position in an octal game is more likely to *not* have a rare value.
     /// It is therefore worthwhile to first check the values of all successsors in the from x ^ y,
@@ -456,100 +886,242 @@ impl Game {
     /// two sens fulfil the criteria outlined above, the algorithm works correctly (although it may
     /// work even slower than the naive).  For instance, if we assume that all values are in R, and
     /// C is an empty set, then this algorithm still correctly identifies nimbers.
-    pub fn rc(&mut self, n: usize) -> Nimber {
-        self.bits.seen.zero_bits();
-
-        self.set_seen_bits_from_some_moves(n);
-        self.set_0th_bit_if_can_be_divided_in_half(n);
-        self.iterate_over_r_xor_c(n);
-
+    pub fn rc(&mut self, n: usize) -> usize {
+        self.set_seen_bits(n);
         self.prove(n)
     }
 
-    pub fn rc_back(&mut self, n: usize) -> Nimber {
+    /// Set the values of the successors of n found without `prove`, after which all the
+    /// values smaller than the smallest unset one outside of R are known.
+    ///
+    /// The heaps are read from the front, and past it (in tail mode) from the tail ring and the
+    /// history, which do not support the moves into three heaps and the repeating codes.
+    fn set_seen_bits(&mut self, n: usize) {
         self.bits.seen.zero_bits();
 
-        self.set_seen_bits_from_some_moves_back(n);
         self.set_0th_bit_if_can_be_divided_in_half(n);
-        self.iterate_over_r_xor_c_back(n);
+        if n < self.nimbers.g.len() {
+            with_front!(self.nimbers, |front| set_seen_bits_from_some_moves(
+                &self.rules,
+                &front,
+                n,
+                &mut self.bits.seen,
+            ));
+            self.set_seen_bits_from_triples(n);
+            if !self.repeating.is_empty() {
+                self.set_seen_bits_from_repeating_moves(n);
+            }
+        } else {
+            with_tail!(self.nimbers, n, |tail| {
+                let heaps = Chain(tail, self.history.as_ref());
+                set_seen_bits_from_some_moves(&self.rules, &heaps, n, &mut self.bits.seen);
+            });
+        }
+        self.iterate_over_levels(n);
+    }
 
+    pub fn rc_back(&mut self, n: usize) -> usize {
+        self.set_seen_bits(n);
         self.prove_back(n)
     }
 
+    /// Pair the heaps of every level of the sparse space with all the others.
+    ///
+    /// Every pair except those of two heaps common at all the levels is then seen, and those only
+    /// have values in R, so all the values smaller than the candidate and outside of R are
+    /// eliminated.  The more levels, the larger the lists of heaps to pair, but the smaller R, and
+    /// the fewer values left to `prove`.
+    fn iterate_over_levels(&mut self, n: usize) {
+        for level in 0..self.bits.chain.len() {
+            let unset = self.bits.seen.count_unset();
+            let rare = self.nimbers.rare_at(level);
+            if n < self.nimbers.g.len() {
+                with_front!(self.nimbers, |front| set_seen_bits_from_rare(
+                    &self.rules,
+                    rare,
+                    &front,
+                    n,
+                    self.threads,
+                    self.thresholds.parallel_heaps,
+                    &mut self.bits.seen,
+                ));
+                if !self.repeating.is_empty() {
+                    self.iterate_over_r_xor_c_repeating(n, level);
+                }
+            } else {
+                with_tail!(self.nimbers, n, |tail| {
+                    let heaps = Chain(tail, self.history.as_ref());
+                    set_seen_bits_from_rare(
+                        &self.rules,
+                        rare,
+                        &heaps,
+                        n,
+                        self.threads,
+                        self.thresholds.parallel_heaps,
+                        &mut self.bits.seen,
+                    );
+                });
+            }
+            self.stats.eliminated[level] += unset - self.bits.seen.count_unset();
+        }
+    }
+
     /// Naively compute the nimber g[n] assuming g[0..n] were computed correctly, accodring to the
     /// rules of some octal game, assuming that at n is at least rules.len().
     ///
     /// Assumption that n is at least rules.len() makes it possible to omit some checks (for instance,
     /// there are no more whole moves possible, and some and divide rules are always applicable, since
     /// n is greater than rules.len(0).
-    pub fn naive(&mut self, n: usize) -> Nimber {
+    pub fn naive(&mut self, n: usize) -> usize {
         assert!(n >= self.rules.len());
         self.bits.seen.zero_bits();
 
-        for i in 1..self.rules.len() {
-            if self.rules[i].some {
-                self.bits.seen.set_bit(self.nimbers.g[n - i] as usize);
+        for i in 0..self.rules.len() {
+            if i > 0 && self.rules[i].some {
+                self.bits.seen.set_bit(self.nimbers.g.get(n - i).to_usize());
             }
 
             if self.rules[i].divide {
-                for j in 1..=(n - i) / 2 {
-                    let x = self.nimbers.g[j];
-                    let y = self.nimbers.g[n - i - j];
-                    self.bits.seen.set_bit((x ^ y) as usize);
+                let half = (n - i) / 2;
+                if let Some(g) = self.nimbers.scanned() {
+                    let len = g.len();
+                    let a = &g[1..=half];
+                    let b = &self.nimbers.g_rev[len - n + i..];
+                    for (&x, &y) in a.iter().zip(b) {
+                        self.bits.seen.set_bit((x ^ y).to_usize());
+                    }
+                } else {
+                    for j in 1..=half {
+                        let x = self.nimbers.g.get(j);
+                        let y = self.nimbers.g.get(n - i - j);
+                        self.bits.seen.set_bit((x ^ y).to_usize());
+                    }
                 }
             }
         }
 
-        self.bits.seen.lowest_unset() as Nimber
+        self.set_seen_bits_from_triples(n);
+
+        if !self.repeating.is_empty() {
+            for i in self.rules.len()..=n {
+                let rule = self.rule(i);
+                if rule.all && i == n {
+                    self.bits.seen.set_bit(0);
+                }
+
+                if rule.some && i < n {
+                    self.bits.seen.set_bit(self.nimbers.g.get(n - i).to_usize());
+                }
+
+                if rule.divide {
+                    for j in 1..=(n - i) / 2 {
+                        let x = self.nimbers.g.get(j);
+                        let y = self.nimbers.g.get(n - i - j);
+                        self.bits.seen.set_bit((x ^ y).to_usize());
+                    }
+                }
+
+                if rule.divide3 {
+                    for_each_triple(&self.nimbers.g, n - i, |x| self.bits.seen.set_bit(x));
+                }
+            }
+        }
+
+        self.bits.seen.lowest_unset()
     }
 
-    pub fn set_next_g_n(&mut self, n: usize, nim: Nimber) {
-        self.nimbers.g[n] = nim;
+    /// Set the values of all successors of n, which divide the heap into three.
+    ///
+    /// The sparse space phenomenon does not help here, since x ^ y ^ z is common when all of x, y
+    /// and z are common, so all the triples have to be enumerated before a common candidate can
+    /// be chosen.
+    fn set_seen_bits_from_triples(&mut self, n: usize) {
+        for i in 0..self.rules.len() {
+            if self.rules[i].divide3 {
+                let seen = &mut self.bits.seen;
+                match self.nimbers.g.as_slice() {
+                    Some(g) => for_each_triple(g, n - i, |x| seen.set_bit(x)),
+                    None => for_each_triple(&self.nimbers.g, n - i, |x| seen.set_bit(x)),
+                }
+            }
+        }
+    }
+
+    /// Convert the value of heap n to N, failing if it does not fit.
+    fn fit(n: usize, nim: usize) -> Result<N, Overflow> {
+        N::try_from_usize(nim).ok_or(Overflow {
+            n,
+            nim,
+            bits: N::BITS,
+        })
+    }
+
+    pub fn set_next_g_n(&mut self, n: usize, nim: usize) -> Result<(), Overflow> {
+        let nim = Self::fit(n, nim)?;
+        self.nimbers.set(n, nim);
+        self.nimbers.first.record(n, nim.to_usize());
 
         if nim >= self.stats.largest_nimber {
             self.stats.largest_nimber_index = n;
         }
+        let mut filed = false;
         if nim > self.stats.largest_nimber {
             self.stats.largest_nimber = nim;
-            println!("resizing {}", nim);
-            self.resize(n);
-            println!("resizing finished");
+            if self.verbose {
+                println!("resizing {}", nim);
+            }
+            filed = self.resize(n);
+            if self.verbose {
+                println!("resizing finished");
+            }
         }
 
-        self.stats.frequencies[nim as usize] += 1;
+        self.stats.frequencies[nim.to_usize()] += 1;
 
-        if n < self.nimbers.g.len() && self.bits.rare.get(nim as usize) {
-            self.nimbers.rare.push((n, nim));
+        // a new chain filed the heap n already
+        if !filed && n < self.nimbers.g.len() {
+            self.push_rare(n, nim);
         }
 
         if n.is_power_of_two() {
             self.resize(n);
         }
+
+        Ok(())
     }
 
-    pub fn set_next_g_back(&mut self, n: usize, nim: Nimber) {
-        let loc = n % self.nimbers.g_back.len();
-        self.nimbers.g_back[loc] = nim;
+    pub fn set_next_g_back(&mut self, n: usize, nim: usize) -> Result<(), Overflow> {
+        let nim = Self::fit(n, nim)?;
+        self.nimbers.set_last(n, nim);
 
         if nim >= self.stats.largest_nimber {
             self.stats.largest_nimber_index = n;
         }
+        let mut filed = false;
         if nim > self.stats.largest_nimber {
             self.stats.largest_nimber = nim;
-            println!("resizing {}", nim);
-            self.resize(n);
-            println!("resizing finished");
+            if self.verbose {
+                println!("resizing {}", nim);
+            }
+            filed = self.resize(n);
+            if self.verbose {
+                println!("resizing finished");
+            }
         }
 
-        self.stats.frequencies[nim as usize] += 1;
+        self.stats.frequencies[nim.to_usize()] += 1;
 
-        if n < self.nimbers.g.len() && self.bits.rare.get(nim as usize) {
-            self.nimbers.rare.push((n, nim));
+        // unlike in the front, rare values are not expected here, but the game may still have
+        // some, which have to be paired with the common values from now on
+        if !filed {
+            self.push_rare(n, nim);
         }
 
         if n.is_power_of_two() {
             self.resize(n);
         }
+
+        Ok(())
     }
 
     pub fn dump_stats(&self, n: usize, start: &Instant) {
@@ -564,8 +1136,9 @@ impl Game {
             self.stats.latest_rare,
             self.stats.latest_rare_index,
             n,
-            self.nimbers.g[n],
+            self.nimbers.g.get(n),
         );
+        self.dump_levels();
     }
 
     pub fn dump_stats_back(&self, skipped: usize, n: usize, start: &Instant) {
@@ -580,20 +1153,34 @@ impl Game {
             self.stats.latest_rare,
             self.stats.latest_rare_index,
             n,
-            self.nimbers.g_back[n % self.nimbers.g_back.len()],
+            self.nimbers.g_back.get(n % self.nimbers.g_back.len()),
         );
+        self.dump_levels();
+    }
+
+    /// Print the number of heaps of each level of the sparse space, and how many values each
+    /// level eliminated.
+    fn dump_levels(&self) {
+        for (level, eliminated) in self.stats.eliminated.iter().enumerate() {
+            println!(
+                "  level {}: heaps={}, eliminated={}",
+                level,
+                self.nimbers.rare_at(level).len(),
+                eliminated
+            );
+        }
     }
 
     pub fn occasional_info(&mut self, n: usize, start: &Instant) {
         let max = self.nimbers.g.len();
-        let inc = if max > (2 as usize).pow(30) {
+        let inc = if max > 2_usize.pow(30) {
             max / 1000
         } else {
             max / 100
         };
 
         if n % 100000 == 0 {
-            self.dump_stats(n, &start);
+            self.dump_stats(n, start);
         }
 
         if n.is_power_of_two() {
@@ -615,7 +1202,7 @@ impl Game {
 
     pub fn occasional_info_back(&mut self, skipped: usize, n: usize, start: &Instant) {
         if n % 100000 == 0 {
-            self.dump_stats_back(skipped, n, &start);
+            self.dump_stats_back(skipped, n, start);
         }
 
         if n.is_power_of_two() {
@@ -623,19 +1210,21 @@ impl Game {
         }
     }
 
-    pub fn calc_rc(&mut self, n: usize) {
+    /// Compute and store the value of heap n, unless it does not fit in N, in which case the
+    /// game has to be `widen`ed before computing it again.
+    pub fn calc_rc(&mut self, n: usize) -> Result<(), Overflow> {
         let nim = self.rc(n);
-        self.set_next_g_n(n, nim);
+        self.set_next_g_n(n, nim)
     }
 
-    pub fn calc_rc_back(&mut self, n: usize) {
+    pub fn calc_rc_back(&mut self, n: usize) -> Result<(), Overflow> {
         let nim = self.rc_back(n);
-        self.set_next_g_back(n, nim);
+        self.set_next_g_back(n, nim)
     }
 
-    pub fn calc_naive(&mut self, n: usize) {
+    pub fn calc_naive(&mut self, n: usize) -> Result<(), Overflow> {
         let nim = self.naive(n);
-        self.set_next_g_n(n, nim);
+        self.set_next_g_n(n, nim)
     }
 
     pub fn dump_freqs(&self, n: usize, start: &Instant) {
@@ -657,158 +1246,210 @@ impl Game {
         println!("{}", formatted_json);
     }
 
-    fn resize(&mut self, n: usize) {
+    /// Choose the sparse space again for the frequencies so far, and return whether the heaps
+    /// 1..=n were filed again into the levels of the new chain, which happens when it changed.
+    fn resize(&mut self, n: usize) -> bool {
         self.stats.resize_frequencies();
-        self.bits.resize(self.stats.largest_nimber);
-        self.bits.rare = self.stats.gen_rares();
-        self.nimbers.rare.clear();
-        for i in 1..std::cmp::min(n + 1, self.nimbers.g.len()) {
-            if self.bits.rare.get(self.nimbers.g[i] as usize) {
-                self.nimbers.rare.push((i, self.nimbers.g[i]));
+        self.bits.resize(self.stats.largest_nimber.to_usize());
+        let chain = self.stats.gen_chain(self.levels);
+        let previous = std::mem::replace(&mut self.bits.chain, chain);
+        self.bits.rare = self.stats.gen_rares(&self.bits.chain);
+        self.stats.eliminated.resize(self.bits.chain.len(), 0);
+
+        // the heaps of each level only change with the chain
+        if previous == self.bits.chain {
+            return false;
+        }
+
+        // the values of the heaps after the front do not change, so the ones already filed are
+        // kept, unless a value common at all the levels of the previous chain is rare now: any
+        // heap of the tail may have it, and finding them reads the whole tail from the history
+        let max_full_memory = self.nimbers.g.len();
+        let newly_rare = self.stats.frequencies.iter().enumerate().any(|(x, &f)| {
+            f > 0 && previous.iter().all(|level| level.is_common(x)) && self.bits.level(x).is_some()
+        });
+        let mut tail: Vec<(usize, N)> = vec![];
+        if n >= max_full_memory {
+            let heaps = Chain(self.nimbers.tail(n + 1), self.history.as_ref());
+            if newly_rare {
+                tail = (max_full_memory..n).map(|i| (i, heaps.get(i))).collect();
+            } else {
+                tail = std::iter::once(&self.nimbers.rare)
+                    .chain(&self.nimbers.levels)
+                    .flatten()
+                    .filter(|&&(i, _)| i >= max_full_memory && i < n)
+                    .copied()
+                    .collect();
+                tail.sort_unstable_by_key(|&(i, _)| i);
             }
+            // the heap n may not be filed yet, and its value is in the tail ring
+            tail.push((n, heaps.get(n)));
+        }
+
+        self.nimbers.rare.clear();
+        self.nimbers.levels = vec![vec![]; self.bits.chain.len() - 1];
+        for i in 1..std::cmp::min(n + 1, max_full_memory) {
+            self.push_rare(i, self.nimbers.g.get(i));
         }
+        for (i, x) in tail {
+            self.push_rare(i, x);
+        }
+        true
     }
 
-    fn prove(&mut self, n: usize) -> Nimber {
-        let first_common = self
-            .bits
-            .seen
-            .find_first_unset_also_unset_in(&self.bits.rare);
+    /// Add the heap n to the heaps of the level its value stops being common at, if any.
+    fn push_rare(&mut self, n: usize, nim: N) {
+        if let Some(level) = self.bits.level(nim.to_usize()) {
+            self.nimbers.rare_at_mut(level).push((n, nim));
+        }
+    }
 
-        let mut mex = self.bits.seen.copy_up_to_inclusive(first_common + 1);
-        let mut remaining_unset = mex.count_unset() - 1; // -1 for mex[first_common]
+    fn prove(&mut self, n: usize) -> usize {
+        let proof = self.prove_seen(n, &self.bits.seen);
+        self.record_proof(n, proof)
+    }
 
-        for i in 1..self.rules.len() {
-            if remaining_unset == 0 {
-                return first_common as Nimber;
+    /// Update the statistics with the proof of the value of heap n, and return the value.
+    fn record_proof(&mut self, n: usize, proof: Proof) -> usize {
+        match proof {
+            Proof::Common(nim, pairs) => {
+                self.stats.prev_values = std::cmp::max(self.stats.prev_values, pairs);
+                nim
             }
-
-            if self.rules[i].divide {
-                for j in 1..=(n - i) / 2 {
-                    let a = self.nimbers.g[j];
-                    let b = self.nimbers.g[n - i - j];
-                    let loc = (a ^ b) as usize;
-
-                    if loc < first_common && !mex.get(loc) {
-                        // a rare value smaller than first_common and not previously observed found
-                        mex.set_bit(loc);
-                        remaining_unset -= 1;
-                        if remaining_unset == 0 {
-                            // all smaller values than first_common found, the value is the smallest
-                            // not observed common
-                            self.stats.prev_values = std::cmp::max(self.stats.prev_values, j);
-                            return first_common as Nimber;
-                            // break
-                        }
-                    }
-                }
+            Proof::Rare(nim) => {
+                self.stats.latest_rare = N::from_usize(nim);
+                self.stats.latest_rare_index = n;
+                nim
             }
         }
+    }
 
-        let nim = mex.lowest_unset() as Nimber;
-        self.stats.latest_rare = nim;
-        self.stats.latest_rare_index = n;
-
-        nim
+    /// Prove the value of heap n, given the values of its successors found by the sparse space
+    /// in seen.
+    fn prove_seen(&self, n: usize, seen: &B) -> Proof {
+        with_front!(self.nimbers, |front| self.prove_in(&front, n, seen))
     }
 
-    fn prove_back(&mut self, n: usize) -> Nimber {
-        let first_common = self
-            .bits
-            .seen
-            .find_first_unset_also_unset_in(&self.bits.rare);
+    /// Prove the value of heap n with the pairs of heaps held by the store, given the values of
+    /// its successors found by the sparse space in seen.
+    ///
+    /// The proof of a common value is final, but a rare one is only final if the store holds
+    /// all the heaps before n.
+    fn prove_in(&self, store: &(impl NimberStore<N> + Sync), n: usize, seen: &B) -> Proof {
+        let first_common = seen.find_first_unset_also_unset_in(&self.bits.rare);
 
-        let mut mex = self.bits.seen.copy_up_to_inclusive(first_common + 1);
+        let mut mex = seen.copy_up_to_inclusive(first_common + 1);
         let mut remaining_unset = mex.count_unset() - 1; // -1 for mex[first_common]
 
-        for i in 1..self.rules.len() {
+        for i in 0..self.rules.len() {
             if remaining_unset == 0 {
-                return first_common as Nimber;
+                return Proof::Common(first_common, 0);
             }
 
             if self.rules[i].divide {
-                let shift = (self.nimbers.g_back.len() + n - i) % self.nimbers.g_back.len();
-                let mut f = 1;
-                for j in (1..=shift).rev() {
-                    let a = self.nimbers.g[f];
-                    f += 1;
-                    let b = self.nimbers.g_back[j];
-                    let loc = (a ^ b) as usize;
-
-                    if loc < first_common && !mex.get(loc) {
-                        // a rare value smaller than first_common and not previously observed found
-                        mex.set_bit(loc);
-                        remaining_unset -= 1;
-                        if remaining_unset == 0 {
-                            // all smaller values than first_common found, the value is the smallest
-                            // not observed common
-                            self.stats.prev_values = std::cmp::max(self.stats.prev_values, f);
-                            return first_common as Nimber;
-                            // break
-                        }
-                    }
+                // the pairs (j, n - i - j) from j = 1 on, as long as the store holds both heaps
+                let m = n - i;
+                let (low, high) = (store.held(1), store.held(m - 1));
+                let pairs = if low.is_empty() || high.is_empty() {
+                    0
+                } else {
+                    std::cmp::min(m / 2, std::cmp::min(low.end - 1, m - high.start))
+                };
+
+                // long proofs continue in parallel, after the pairs most of them end within
+                let serial = if self.threads > 1 {
+                    std::cmp::min(pairs, self.thresholds.serial_pairs)
+                } else {
+                    pairs
+                };
+
+                let last = prove_pairs(
+                    store,
+                    m,
+                    1..serial + 1,
+                    first_common,
+                    &mut mex,
+                    &mut remaining_unset,
+                );
+                if let Some(j) = last {
+                    return Proof::Common(first_common, j);
                 }
 
-                for j in (shift + 1..self.nimbers.g_back.len()).rev() {
-                    let a = self.nimbers.g[f];
-                    f += 1;
-                    let b = self.nimbers.g_back[j];
-                    let loc = (a ^ b) as usize;
-
-                    if loc < first_common && !mex.get(loc) {
-                        // a rare value smaller than first_common and not previously observed found
-                        mex.set_bit(loc);
-                        remaining_unset -= 1;
-                        if remaining_unset == 0 {
-                            // all smaller values than first_common found, the value is the smallest
-                            // not observed common
-                            self.stats.prev_values = std::cmp::max(self.stats.prev_values, f);
-                            return first_common as Nimber;
-                            // break
-                        }
+                if serial < pairs {
+                    let rest = serial + 1..pairs + 1;
+                    let last = self.prove_parallel(
+                        store,
+                        m,
+                        rest,
+                        first_common,
+                        &mut mex,
+                        &mut remaining_unset,
+                    );
+                    if let Some(j) = last {
+                        return Proof::Common(first_common, j);
                     }
                 }
             }
         }
 
-        let nim = mex.lowest_unset() as Nimber;
-        self.stats.latest_rare = nim;
-        self.stats.latest_rare_index = n;
-        panic!("unexpectedly, larger rare value found! G({}) = {}", n, nim)
+        if !self.repeating.is_empty() {
+            if let Some(a) = self.prove_repeating(n, first_common, &mut mex, &mut remaining_unset) {
+                return Proof::Common(first_common, a);
+            }
+        }
+
+        Proof::Rare(mex.lowest_unset())
     }
 
-    fn iterate_over_r_xor_c(&mut self, n: usize) {
-        // iterate over x ^ y such that x is in R
-        for i in 1..self.rules.len() {
-            if self.rules[i].divide {
-                let mut m = self.nimbers.rare.len();
-                while m > 0 && n <= i + self.nimbers.rare[m - 1].0 {
-                    m -= 1;
+    fn prove_back(&mut self, n: usize) -> usize {
+        let proof = with_tail!(self.nimbers, n, |tail| self.prove_in(
+            &tail,
+            n,
+            &self.bits.seen
+        ));
+        match proof {
+            Proof::Rare(_) => {
+                // a rare value, unless a pair of heaps from the middle of the sequence, which is
+                // no longer in memory, is a successor with the same value
+                let first_common = self
+                    .bits
+                    .seen
+                    .find_first_unset_also_unset_in(&self.bits.rare);
+                let nim = self.prove_back_full(n);
+                if nim == first_common {
+                    // all the pairs were needed to find the values smaller than the candidate
+                    return self.record_proof(n, Proof::Common(nim, n / 2));
                 }
-                for (idx, x) in self.nimbers.rare[0..m].iter() {
-                    let s = (x ^ self.nimbers.g[n - i - idx]) as usize;
-                    self.bits.seen.set_bit(s);
+                if self.verbose {
+                    println!("rare value found in tail mode: G({}) = {}", n, nim);
                 }
+                self.record_proof(n, Proof::Rare(nim))
             }
+            proof => self.record_proof(n, proof),
         }
     }
 
-    fn iterate_over_r_xor_c_back(&mut self, n: usize) {
-        // iterate over x ^ y such that x is in R
-        for i in 1..self.rules.len() {
-            if self.rules[i].divide {
-                // we assume that nimbers.rare's last value cannot exceed n - i
-                for (idx, x) in self.nimbers.rare.iter() {
-                    let s = (x ^ self.nimbers.last(n - i - idx)) as usize;
-                    self.bits.seen.set_bit(s);
+    /// Compute the value of the heap n in tail mode from all its successors, reading the pairs
+    /// of heaps which are no longer in memory from the history.
+    fn prove_back_full(&mut self, n: usize) -> usize {
+        with_tail!(self.nimbers, n, |tail| {
+            let heaps = Chain(tail, self.history.as_ref());
+            for i in 0..self.rules.len() {
+                if self.rules[i].divide {
+                    for a in 1..=(n - i) / 2 {
+                        let x = heaps.get(a) ^ heaps.get(n - i - a);
+                        self.bits.seen.set_bit(x.to_usize());
+                    }
                 }
             }
-        }
+        });
+
+        self.bits.seen.lowest_unset()
     }
 
     fn set_0th_bit_if_can_be_divided_in_half(&mut self, n: usize) {
         // set an obvious 0, if the game has a dividing move to any pair (x, x)
-        for i in 1..self.rules.len() {
+        for i in 0..self.rules.len() {
             if self.rules[i].divide && (n - i) & 1 == 0 {
                 self.bits.seen.set_bit(0);
                 break;
@@ -816,25 +1457,37 @@ impl Game {
         }
     }
 
+    /// Check if the game with a finite code is periodic using only g[0..n], and print the period
+    /// or the best candidate.  Repeating codes are arithmetic periodic instead, see
+    /// `check_arithmetic_period`.
     pub fn check_period(&self, n: usize) -> bool {
-        for period in 1..n {
-            let mut start = n - period;
-            while start > 0 && self.nimbers.g[start - 1] == self.nimbers.g[start - 1 + period] {
-                start -= 1;
-            }
-
-            if n >= 2 * start + 2 * period + self.rules.len() - 1 {
+        match period::find_period(&self.nimbers.front(), &self.rules, n) {
+            Search::Period(start, period) => {
                 println!("period start: {}\n", start);
                 println!("period: {}\n", period);
-                return true;
+                true
+            }
+            Search::Candidate(candidate) => {
+                // how far the run is from confirming the most promising period
+                if let Some((start, period)) = candidate {
+                    println!("candidate period start: {}\n", start);
+                    println!("candidate period: {}\n", period);
+                    println!(
+                        "repeated for {} heaps, confirmed after {} heaps if it goes on",
+                        n.saturating_sub(start + period),
+                        periodicity_bound(&self.rules, start, period)
+                    );
+                }
+                false
             }
         }
-        return false;
     }
 }
 
 #[cfg(test)]
 mod test {
+    use super::simd::Scan;
+    use super::testing::{check_against_naive, front, TempDir};
     use super::*;
     use phf::phf_map;
 
@@ -849,21 +1502,25 @@ mod test {
                     all: false,
                     some: false,
                     divide: false,
+                    divide3: false,
                 },
                 Rule {
                     all: false,
                     some: false,
                     divide: false,
+                    divide3: false,
                 },
                 Rule {
                     all: true,
                     some: true,
                     divide: false,
+                    divide3: false,
                 },
                 Rule {
                     all: false,
                     some: false,
                     divide: true,
+                    divide3: false,
                 },
             ]
         );
@@ -877,59 +1534,97 @@ mod test {
                     all: false,
                     some: false,
                     divide: false,
+                    divide3: false,
                 },
                 Rule {
                     all: false,
                     some: false,
                     divide: false,
+                    divide3: false,
                 },
                 Rule {
                     all: true,
                     some: false,
-                    divide: false
+                    divide: false,
+                    divide3: false,
                 },
                 Rule {
                     all: false,
                     some: true,
                     divide: false,
+                    divide3: false,
                 },
                 Rule {
                     all: true,
                     some: true,
                     divide: false,
+                    divide3: false,
                 },
                 Rule {
                     all: false,
                     some: false,
                     divide: true,
+                    divide3: false,
                 },
                 Rule {
                     all: true,
                     some: false,
                     divide: true,
+                    divide3: false,
                 },
                 Rule {
                     all: false,
                     some: true,
                     divide: true,
+                    divide3: false,
                 },
                 Rule {
                     all: true,
                     some: true,
                     divide: true,
+                    divide3: false,
                 },
                 Rule {
                     all: false,
                     some: false,
                     divide: false,
+                    divide3: false,
                 },
             ]
         );
     }
 
+    #[test]
+    fn test_hex_game_to_rules() {
+        let rules = rules_from_str("0.B4");
+
+        assert_eq!(
+            rules[1],
+            Rule {
+                all: true,
+                some: true,
+                divide: false,
+                divide3: true,
+            }
+        );
+        assert_eq!(
+            rules[2],
+            Rule {
+                all: false,
+                some: false,
+                divide: true,
+                divide3: false,
+            }
+        );
+        assert_eq!(rules_from_str("0.F"), rules_from_str("0.f"));
+        assert_eq!(max_heaps(&rules_from_str("0.034")), 2);
+        assert_eq!(max_heaps(&rules_from_str("0.48")), 3);
+    }
+
     /// initial values taken from Achim Flammenkamp webpage:
     /// http://wwwhomes.uni-bielefeld.de/achim/octal.html
-    static GAMES_NIMBERS: phf::Map<&'static str, [Nimber; 16]> = phf_map! {
+    /// (values of games with a nonzero d0 digit computed by exhaustive enumeration of successors)
+    static GAMES_NIMBERS: phf::Map<&'static str, [u16; 16]> = phf_map! {
         "0.004" =>  [0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 0, 3, 3, 3],
         "0.005" =>  [0, 0, 0, 1, 0, 1, 1, 2, 2, 2, 0, 3, 3, 4, 1, 1],
         "0.006" =>  [0, 0, 0, 0, 1, 1, 1, 2, 2, 2, 0, 3, 3, 1, 1, 1],
@@ -1012,6 +1707,37 @@ mod test {
         "0.764" =>  [0, 1, 0, 2, 3, 4, 5, 1, 6, 2, 3, 4, 5, 7, 6, 8],
         "0.774" =>  [0, 1, 2, 3, 1, 4, 5, 6, 7, 1, 3, 2, 8, 9, 5, 4],
         "0.776" =>  [0, 1, 2, 3, 4, 1, 6, 3, 2, 1, 6, 7, 4, 5, 8, 1],
+        "4.007" =>  [0, 0, 1, 2, 1, 2, 3, 4, 5, 4, 1, 3, 2, 8, 2, 5],
+        "4.037" =>  [0, 0, 1, 2, 3, 4, 5, 6, 1, 3, 2, 7, 4, 1, 6, 5],
+        "4.07" =>  [0, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2],
+        "4.34" =>  [0, 1, 2, 0, 2, 0, 2, 0, 2, 0, 2, 0, 2, 0, 2, 0],
+        "4.4" =>  [0, 0, 1, 2, 3, 1, 4, 3, 2, 1, 4, 2, 6, 4, 1, 2],
+        "4.7" =>  [0, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    };
+
+    /// values computed by exhaustive enumeration of all successors
+    static HEX_GAMES_NIMBERS: phf::Map<&'static str, [u16; 16]> = phf_map! {
+        "0.08" =>  [0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3],
+        "0.1C" =>  [0, 1, 0, 0, 1, 0, 2, 2, 2, 2, 4, 4, 4, 4, 6, 6],
+        "0.48" =>  [0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7],
+        "0.8" =>  [0, 0, 0, 0, 1, 1, 1, 2, 2, 2, 3, 3, 3, 4, 4, 4],
+        "0.9" =>  [0, 1, 0, 0, 0, 1, 2, 2, 2, 3, 4, 4, 4, 5, 6, 6],
+        "0.B4" =>  [0, 1, 0, 1, 2, 3, 2, 4, 5, 1, 6, 7, 2, 3, 2, 1],
+        "0.E" =>  [0, 0, 1, 2, 3, 4, 1, 5, 3, 2, 1, 5, 8, 2, 6, 5],
+        "0.F8" =>  [0, 1, 0, 1, 0, 2, 3, 2, 3, 4, 5, 4, 5, 6, 7, 8],
+    };
+
+    /// values computed by exhaustive enumeration of all successors
+    static REPEATING_GAMES_NIMBERS: phf::Map<&'static str, [u16; 16]> = phf_map! {
+        "0.(12)" =>  [0, 1, 0, 2, 1, 3, 2, 4, 3, 5, 4, 6, 5, 7, 6, 8],
+        "0.(14)" =>  [0, 1, 0, 1, 1, 2, 1, 2, 2, 3, 2, 4, 4, 4, 4, 6],
+        "0.(4)" =>  [0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7],
+        "0.(C)" =>  [0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7],
+        "0.0(3)" =>  [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7],
+        "0.1(37)" =>  [0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8],
+        "0.3(1)" =>  [0, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+        "0.7(4)" =>  [0, 1, 0, 1, 2, 3, 2, 4, 5, 4, 6, 7, 8, 9, 8, 10],
+        "4.(3)" =>  [0, 1, 2, 4, 3, 5, 6, 8, 7, 9, 10, 12, 11, 13, 14, 16],
     };
 
     #[test]
@@ -1019,7 +1745,7 @@ mod test {
         for (rules_str, res) in GAMES_NIMBERS.into_iter() {
             let initial_len = rules_str.len() - 1; // -1 for '.'
 
-            let mut g = Game::new(rules_str, initial_len, 0);
+            let mut g = Game::<u16>::new(rules_str.parse().unwrap(), initial_len, 0);
             g.init();
 
             assert_eq!(g.nimbers.g, res[0..initial_len]);
@@ -1030,48 +1756,548 @@ mod test {
     fn test_naive() {
         for (rules_str, res) in GAMES_NIMBERS.into_iter() {
             let max = 16;
-            let mut g = Game::new(rules_str, max, 0);
+            let mut g = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
             g.init();
 
             for n in g.rules.len()..max {
                 let nim = g.naive(n);
-                g.set_next_g_n(n, nim);
+                g.set_next_g_n(n, nim).unwrap();
             }
 
             assert_eq!(g.nimbers.g, res);
         }
     }
 
+    fn rc_nimbers<N: Nimber, B: BitSet>(rules_str: &str, max: usize) -> Vec<usize> {
+        let mut g = Game::<N, B>::new(rules_str.parse().unwrap(), max, 0);
+        g.init();
+
+        for n in g.rules.len()..max {
+            g.calc_rc(n).unwrap();
+        }
+
+        g.nimbers.g.iter().map(|x| x.to_usize()).collect()
+    }
+
+    #[test]
+    fn test_widths() {
+        for (rules_str, res) in GAMES_NIMBERS.into_iter() {
+            let res: Vec<usize> = res.iter().map(|&x| x as usize).collect();
+            assert_eq!(
+                rc_nimbers::<u8, VecBin>(rules_str, 16),
+                res,
+                "for game {}",
+                rules_str
+            );
+            assert_eq!(
+                rc_nimbers::<u32, VecBin>(rules_str, 16),
+                res,
+                "for game {}",
+                rules_str
+            );
+            assert_eq!(
+                rc_nimbers::<u64, VecBin>(rules_str, 16),
+                res,
+                "for game {}",
+                rules_str
+            );
+            assert_eq!(
+                rc_nimbers::<u128, VecBin>(rules_str, 16),
+                res,
+                "for game {}",
+                rules_str
+            );
+        }
+
+        for rules_str in ["0.034", "0.16", "4.7"] {
+            let nimbers = rc_nimbers::<u16, VecBin>(rules_str, 1000);
+            assert_eq!(rc_nimbers::<u8, VecBin>(rules_str, 1000), nimbers);
+            assert_eq!(rc_nimbers::<u64, VecBin>(rules_str, 1000), nimbers);
+        }
+    }
+
+    #[test]
+    fn test_bitsets() {
+        let games = GAMES_NIMBERS
+            .entries()
+            .chain(HEX_GAMES_NIMBERS.entries())
+            .chain(REPEATING_GAMES_NIMBERS.entries());
+        for (rules_str, res) in games {
+            let max = 120;
+            let res: Vec<usize> = res.iter().map(|&x| x as usize).collect();
+            let nimbers = rc_nimbers::<u16, VecBin>(rules_str, max);
+            assert_eq!(nimbers[0..16], res, "for game {}", rules_str);
+            for other in [
+                rc_nimbers::<u16, FixedBin<u32>>(rules_str, max),
+                rc_nimbers::<u16, FixedBin<u64>>(rules_str, max),
+                rc_nimbers::<u16, FixedBin<u128>>(rules_str, max),
+            ] {
+                assert_eq!(other, nimbers, "for game {}", rules_str);
+            }
+        }
+    }
+
+    #[test]
+    fn test_widen() {
+        for rules_str in ["0.034", "0.16", "4.7"] {
+            let max = 1000;
+            let half = max / 2;
+
+            let mut narrow = Game::<u8>::new(rules_str.parse().unwrap(), max, 0);
+            narrow.init();
+            for n in narrow.rules.len()..half {
+                narrow.calc_rc(n).unwrap();
+            }
+            // a value which does not fit is not stored
+            let overflow = Overflow {
+                n: half,
+                nim: 256,
+                bits: 8,
+            };
+            assert_eq!(narrow.set_next_g_n(half, 256), Err(overflow));
+
+            let mut g: Game<u16> = narrow.widen();
+            for n in half..max {
+                g.calc_rc(n).unwrap();
+            }
+
+            let mut wide = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
+            wide.init();
+            for n in wide.rules.len()..max {
+                wide.calc_rc(n).unwrap();
+            }
+
+            assert_eq!(g.nimbers.g, wide.nimbers.g, "for game {}", rules_str);
+            assert_eq!(g.nimbers.rare, wide.nimbers.rare, "for game {}", rules_str);
+            assert_eq!(g.stats, wide.stats, "for game {}", rules_str);
+        }
+    }
+
+    #[test]
+    fn test_rc_with_naive_d0() {
+        for (rules_str, _res) in GAMES_NIMBERS.into_iter() {
+            if rules_str.starts_with("0.") {
+                continue;
+            }
+
+            let max = 1000;
+            let mut g = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
+            g.init();
+
+            for n in g.rules.len()..max {
+                let nim_naive = g.naive(n);
+                let nim_rc = g.rc(n);
+                g.set_next_g_n(n, nim_rc).unwrap();
+                assert_eq!(nim_naive, nim_rc, " for game {} at {}", rules_str, n);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rc_back_with_naive() {
+        let dir = TempDir::new("back");
+
+        // games which do not have any rare values after the first max_full_memory nimbers
+        for rules_str in ["0.156", "0.45", "4.4", "4.7"] {
+            let g: Game<u16> = check_against_naive(rules_str, 1024, 4096, &dir, |_| {});
+            assert!(
+                g.nimbers.rare.iter().all(|&(i, _)| i < 1024),
+                "for game {}",
+                rules_str
+            );
+        }
+    }
+
+    #[test]
+    fn test_rc_back_with_history() {
+        let dir = TempDir::new("history");
+
+        // games with rare values after the first max_full_memory nimbers
+        for rules_str in ["0.104", "0.135", "0.163", "0.224"] {
+            let (max_full_memory, max) = (256, 2048);
+            let full: Game<u16> = front(rules_str, max, |_| {});
+            let g: Game<u16> = check_against_naive(rules_str, max_full_memory, max, &dir, |_| {});
+
+            assert!(
+                g.nimbers.rare.iter().any(|&(i, _)| i >= max_full_memory),
+                "for game {}",
+                rules_str
+            );
+            // each heap is filed once, as in the front, however the chain changed in tail mode
+            assert!(
+                g.nimbers.rare.windows(2).all(|w| w[0].0 < w[1].0),
+                "for game {}",
+                rules_str
+            );
+            assert_eq!(g.nimbers.rare, full.nimbers.rare, "for game {}", rules_str);
+            // common values proven by reading the whole history are not reported as rare
+            assert_eq!(
+                (g.stats.latest_rare, g.stats.latest_rare_index),
+                (full.stats.latest_rare, full.stats.latest_rare_index),
+                "for game {}",
+                rules_str
+            );
+        }
+    }
+
+    #[test]
+    fn test_hex_naive() {
+        for (rules_str, res) in HEX_GAMES_NIMBERS.into_iter() {
+            let max = 16;
+            let mut g = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
+            g.init();
+
+            for n in g.rules.len()..max {
+                let nim = g.naive(n);
+                g.set_next_g_n(n, nim).unwrap();
+            }
+
+            assert_eq!(g.nimbers.g, res, " for game {}", rules_str);
+        }
+    }
+
+    #[test]
+    fn test_hex_rc_with_naive() {
+        for (rules_str, _res) in HEX_GAMES_NIMBERS.into_iter() {
+            let max = 200;
+            let mut g = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
+            g.init();
+
+            for n in g.rules.len()..max {
+                let nim_naive = g.naive(n);
+                let nim_rc = g.rc(n);
+                g.set_next_g_n(n, nim_rc).unwrap();
+                assert_eq!(nim_naive, nim_rc, " for game {} at {}", rules_str, n);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hex_periodicity_bound() {
+        let octal = rules_from_str("0.034");
+        let hex = rules_from_str("0.B4");
+
+        assert_eq!(periodicity_bound(&octal, 10, 4), 2 * 10 + 2 * 4 + 3);
+        assert_eq!(periodicity_bound(&hex, 10, 4), 3 * 10 + 3 * 4 + 2);
+    }
+
+    #[test]
+    fn test_repeating_naive() {
+        for (rules_str, res) in REPEATING_GAMES_NIMBERS.into_iter() {
+            let max = 16;
+            let mut g = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
+            g.init();
+
+            for n in g.rules.len()..max {
+                let nim = g.naive(n);
+                g.set_next_g_n(n, nim).unwrap();
+            }
+
+            assert_eq!(g.nimbers.g, res, " for game {}", rules_str);
+        }
+    }
+
+    #[test]
+    fn test_repeating_rc_with_naive() {
+        for (rules_str, _res) in REPEATING_GAMES_NIMBERS.into_iter() {
+            let max = 120;
+            let mut g = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
+            g.init();
+
+            for n in g.rules.len()..max {
+                let nim_naive = g.naive(n);
+                let nim_rc = g.rc(n);
+                g.set_next_g_n(n, nim_rc).unwrap();
+                assert_eq!(nim_naive, nim_rc, " for game {} at {}", rules_str, n);
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_arithmetic_period() {
+        for (rules_str, periodic) in [
+            ("0.(3)", true),
+            ("0.(12)", true),
+            ("0.0(3)", true),
+            ("0.1(23)", true),
+            ("0.3(1)", true),
+            // arithmetic periodic, but dividing moves make the sufficient condition unusable
+            ("0.(4)", false),
+        ] {
+            let max = 200;
+            let mut g = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
+            g.init();
+
+            for n in g.rules.len()..max {
+                g.calc_rc(n).unwrap();
+            }
+
+            assert_eq!(
+                g.check_arithmetic_period(max).is_some(),
+                periodic,
+                " for game {}",
+                rules_str
+            );
+        }
+    }
+
     #[test]
     #[ignore]
     fn test_rc_with_naive() {
         for (rules_str, _res) in GAMES_NIMBERS.into_iter() {
             println!("{}", rules_str);
             let max = 10000;
-            let mut g = Game::new(rules_str, max, 0);
+            let mut g = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
             g.init();
 
             for n in g.rules.len()..max {
                 let nim_naive = g.naive(n);
                 let nim_rc = g.rc(n);
-                g.set_next_g_n(n, nim_rc);
+                g.set_next_g_n(n, nim_rc).unwrap();
                 assert_eq!(nim_naive, nim_rc, " for game {} at {}", rules_str, n);
             }
         }
     }
 
+    #[test]
+    fn test_gen_rares() {
+        for rules_str in ["0.034", "0.16", "0.156", "0.376"] {
+            let max = 2000;
+            let mut g = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
+            g.init();
+            for n in g.rules.len()..max {
+                g.calc_rc(n).unwrap();
+            }
+
+            let frequencies = &g.stats.frequencies;
+            let common = |mask: usize| -> usize {
+                (0..frequencies.len())
+                    .filter(|&x| (x & mask).count_ones() % 2 == 1)
+                    .map(|x| frequencies[x])
+                    .sum()
+            };
+            let best = (1..frequencies.len()).map(common).max().unwrap();
+            assert_eq!(common(g.stats.rare_mask()), best, "for game {}", rules_str);
+
+            let rares: VecBin = g.stats.gen_rares(&g.bits.chain);
+            for x in 0..frequencies.len() {
+                for y in 0..frequencies.len() {
+                    // x ^ y is rare if both are rare or both are common
+                    assert_eq!(
+                        rares.get(x ^ y),
+                        rares.get(x) == rares.get(y),
+                        "for game {} at {} ^ {}",
+                        rules_str,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_levels() {
+        for (rules_str, max) in [
+            ("0.034", 1000),
+            ("0.16", 1000),
+            ("0.376", 1000),
+            ("4.7", 1000),
+            ("0.B4", 200),
+            ("0.(14)", 200),
+        ] {
+            let plain = rc_nimbers::<u16, VecBin>(rules_str, max);
+
+            for levels in 2..=3 {
+                let mut g = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
+                g.levels = levels;
+                g.init();
+                for n in g.rules.len()..max {
+                    g.calc_rc(n).unwrap();
+                }
+
+                let nimbers: Vec<usize> = g.nimbers.g.iter().map(|x| x.to_usize()).collect();
+                assert_eq!(
+                    nimbers, plain,
+                    "for game {} with {} levels",
+                    rules_str, levels
+                );
+
+                // R is a subgroup of index 2^levels, unless there are fewer values than that
+                let chain = &g.bits.chain;
+                let len = g.stats.frequencies.len();
+                assert_eq!(
+                    chain.len(),
+                    std::cmp::min(levels, len.trailing_zeros() as usize)
+                );
+                assert_eq!(g.stats.eliminated.len(), chain.len());
+                let rares = (0..len).filter(|&x| g.bits.rare.get(x)).count();
+                assert_eq!(rares, len >> chain.len(), "for game {}", rules_str);
+
+                // every heap is in the list of the level its value stops being common at
+                for (i, x) in g.nimbers.g.iter().enumerate().skip(1) {
+                    let level = g.bits.level(x.to_usize());
+                    let listed = (0..chain.len()).find(|&l| g.nimbers.rare_at(l).contains(&(i, x)));
+                    assert_eq!(listed, level, "for game {} at {}", rules_str, i);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rc_back_with_levels() {
+        let dir = TempDir::new("levels");
+
+        for rules_str in ["0.104", "0.156"] {
+            let (max_full_memory, max) = (256, 2048);
+            let full: Game<u16> = front(rules_str, max, |g| g.levels = 3);
+            let g: Game<u16> =
+                check_against_naive(rules_str, max_full_memory, max, &dir, |g| g.levels = 3);
+
+            for level in 0..3 {
+                assert_eq!(
+                    g.nimbers.rare_at(level),
+                    full.nimbers.rare_at(level),
+                    "for game {} at level {}",
+                    rules_str,
+                    level
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_reversed() {
+        let dir = TempDir::new("reversed");
+
+        for rules_str in ["0.034", "0.156", "0.104", "4.7"] {
+            let (max_full_memory, max) = (512, 2048);
+            let games: Vec<Game<u16>> = [false, true]
+                .into_iter()
+                .map(|reversed| {
+                    check_against_naive(rules_str, max_full_memory, max, &dir, |g| {
+                        if reversed {
+                            g.nimbers.use_reversed();
+                        }
+                    })
+                })
+                .collect();
+
+            // the scan finds the same pairs as the loops over them
+            assert_eq!(games[1].nimbers.reversed(), u16::VECTOR);
+            assert_eq!(games[1].stats, games[0].stats, "for game {}", rules_str);
+            let reversed: Vec<u16> = games[1].nimbers.g_back.iter().rev().collect();
+            assert_eq!(
+                games[1].nimbers.g_back_rev, reversed,
+                "for game {}",
+                rules_str
+            );
+        }
+    }
+
+    #[test]
+    fn test_reversed_scalar() {
+        // the nimbers without a vector scan keep using the loop over the pairs, also once widened
+        let mut g = Nimbers::<u64>::new(16, 16, 0);
+        g.use_reversed();
+        assert!(!g.reversed());
+
+        let mut g = Nimbers::<u16>::new(16, 16, 0);
+        g.use_reversed();
+        assert_eq!(g.widen::<u32>().reversed(), u32::VECTOR);
+        let mut g = Nimbers::<u16>::new(16, 16, 0);
+        g.use_reversed();
+        assert!(!g.widen::<u128>().reversed());
+    }
+
+    #[test]
+    fn test_packed() {
+        let dir = TempDir::new("packed");
+
+        for rules_str in ["0.034", "0.156", "4.7"] {
+            let (max_full_memory, max, saved) = (512, 2048, 1536);
+            let games: Vec<Game<u16>> = [false, true]
+                .into_iter()
+                .map(|packed| {
+                    let dir = dir.join(packed.to_string());
+                    std::fs::create_dir_all(&dir).unwrap();
+                    let g: Game<u16> =
+                        check_against_naive(rules_str, max_full_memory, saved, &dir, |g| {
+                            if packed {
+                                g.nimbers.use_packed();
+                            }
+                        });
+                    g.save_checkpoint(&dir, saved).unwrap();
+
+                    // the packed chunks are read back into the same storage
+                    let mut resumed = Game::<u16>::new(
+                        rules_str.parse().unwrap(),
+                        max_full_memory,
+                        max_full_memory,
+                    );
+                    if packed {
+                        resumed.nimbers.use_packed();
+                    }
+                    let checkpoint = checkpoint::latest(&dir, &g.code).unwrap().unwrap();
+                    resumed.restore(&dir, checkpoint).unwrap();
+                    resumed.use_history(&dir);
+                    for n in saved..max {
+                        resumed.calc_rc_back(n).unwrap();
+                    }
+                    resumed
+                })
+                .collect();
+
+            assert_eq!(
+                games[1].nimbers.g, games[0].nimbers.g,
+                "for game {}",
+                rules_str
+            );
+            assert_eq!(
+                games[1].nimbers.g_back, games[0].nimbers.g_back,
+                "for game {}",
+                rules_str
+            );
+            assert_eq!(games[1].stats, games[0].stats, "for game {}", rules_str);
+
+            // as many bits as the largest value needs, the ring may have had larger ones before
+            let bits = packed::bits_for(games[1].stats.largest_nimber as u128);
+            let bits_of = |nimbers: &Storage<u16>| match nimbers {
+                Storage::Packed(packed) => packed.bits(),
+                _ => panic!("nimbers of game {} are not packed", rules_str),
+            };
+            let largest_bits =
+                |nimbers: &Storage<u16>| packed::bits_for(nimbers.iter().max().unwrap() as u128);
+            assert_eq!(
+                bits_of(&games[1].nimbers.g),
+                largest_bits(&games[1].nimbers.g)
+            );
+            assert!(bits_of(&games[1].nimbers.g_back) >= largest_bits(&games[1].nimbers.g_back));
+            assert!(bits_of(&games[1].nimbers.g_back) <= bits);
+            let header = chunk::read_header(&checkpoint::nimbers_path(
+                &dir.join("true"),
+                rules_str,
+                saved,
+            ))
+            .unwrap();
+            // saved when the values were smaller
+            assert_eq!(header.width, 0, "for game {}", rules_str);
+            assert!(header.bits <= bits, "for game {}", rules_str);
+        }
+    }
+
     #[test]
     #[ignore]
     fn test_rares() {
         for (rules_str, _res) in GAMES_NIMBERS.into_iter() {
             println!("{}", rules_str);
             let max = 10000;
-            let mut g = Game::new(rules_str, max, 0);
+            let mut g = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
             g.init();
 
             for n in g.rules.len()..max {
                 let nim_rc = g.rc(n);
-                g.set_next_g_n(n, nim_rc);
+                g.set_next_g_n(n, nim_rc).unwrap();
             }
 
             for x in 0..(g.stats.largest_nimber + 1).next_power_of_two() {
@@ -0,0 +1,243 @@
+use bitvec::prelude::*;
+
+/// A set of nimbers, used for the values seen among the successors of a heap and for R.
+///
+/// A bitset made for the largest nimber `largest` holds all the values x ^ y of nimbers up to
+/// `largest`, and has at least one unset bit above them.
+pub trait BitSet: Clone + Send + Sync + 'static {
+    /// Name of the backend, as accepted by `octal`.
+    const NAME: &'static str;
+
+    fn make(largest: usize) -> Self;
+    fn set_bit(&mut self, x: usize);
+    fn zero_bits(&mut self);
+    fn get(&self, x: usize) -> bool;
+    fn lowest_unset(&self) -> usize;
+    fn count_unset(&self) -> usize;
+    fn find_first_unset_also_unset_in(&self, other: &Self) -> usize;
+    /// Copy the bits up to x, such that all the unset bits of the copy are smaller than x.
+    fn copy_up_to_inclusive(&self, x: usize) -> Self;
+    /// Set all the bits set in other, which was made for the same largest nimber.
+    fn union_with(&mut self, other: &Self);
+}
+
+/// Names of all the backends.
+pub const BACKENDS: [&str; 4] = [
+    VecBin::NAME,
+    FixedBin::<u32>::NAME,
+    FixedBin::<u64>::NAME,
+    FixedBin::<u128>::NAME,
+];
+
+/// A bitset backed by a bit vector, large enough for nimbers of any size.
+#[derive(Clone)]
+pub struct VecBin {
+    bits: BitVec<u64, Msb0>,
+}
+
+impl BitSet for VecBin {
+    const NAME: &'static str = "bitvec";
+
+    fn make(largest: usize) -> Self {
+        let bs = 2 * largest.next_power_of_two() + 2;
+        Self {
+            bits: bitvec!(u64, Msb0; 0; bs),
+        }
+    }
+
+    #[inline]
+    fn set_bit(&mut self, x: usize) {
+        self.bits.set(x, true);
+    }
+
+    fn zero_bits(&mut self) {
+        self.bits.set_elements(0);
+    }
+
+    #[inline]
+    fn get(&self, x: usize) -> bool {
+        self.bits[x]
+    }
+
+    fn lowest_unset(&self) -> usize {
+        self.bits.first_zero().unwrap()
+    }
+
+    fn count_unset(&self) -> usize {
+        self.bits.count_zeros()
+    }
+
+    fn find_first_unset_also_unset_in(&self, other: &Self) -> usize {
+        for i in 0..other.bits.len() {
+            if !self.get(i) && !other.get(i) {
+                return i;
+            }
+        }
+
+        self.bits.len() - 1
+    }
+
+    fn copy_up_to_inclusive(&self, x: usize) -> Self {
+        Self {
+            bits: self.bits[0..x].to_owned(),
+        }
+    }
+
+    fn union_with(&mut self, other: &Self) {
+        self.bits |= other.bits.as_bitslice();
+    }
+}
+
+/// A bitset fitting in a single integer, which falls back to a `VecBin` when the nimbers do not
+/// fit in it anymore.
+///
+/// Both the seen and the rare bitsets are made for the same largest nimber on every resize, so
+/// they always use the same representation.
+#[derive(Clone)]
+pub enum FixedBin<T> {
+    Fixed(T),
+    Vec(VecBin),
+}
+
+macro_rules! impl_fixed_bin {
+    ($($t:ty => $name:literal),*) => {
+        $(
+            impl BitSet for FixedBin<$t> {
+                const NAME: &'static str = $name;
+
+                fn make(largest: usize) -> Self {
+                    let bs = (largest + 2).next_power_of_two() + 2;
+                    // the bits from bs on are set, so at least one of them is needed to stop
+                    // lowest_unset
+                    if bs < <$t>::BITS as usize {
+                        FixedBin::Fixed(!(0 as $t) << bs)
+                    } else {
+                        FixedBin::Vec(VecBin::make(largest))
+                    }
+                }
+
+                #[inline(always)]
+                fn set_bit(&mut self, x: usize) {
+                    match self {
+                        FixedBin::Fixed(bits) => *bits |= 1 << x,
+                        FixedBin::Vec(bin) => bin.set_bit(x),
+                    }
+                }
+
+                fn zero_bits(&mut self) {
+                    match self {
+                        FixedBin::Fixed(bits) => *bits = 0,
+                        FixedBin::Vec(bin) => bin.zero_bits(),
+                    }
+                }
+
+                #[inline(always)]
+                fn get(&self, x: usize) -> bool {
+                    match self {
+                        FixedBin::Fixed(bits) => bits & 1 << x != 0,
+                        FixedBin::Vec(bin) => bin.get(x),
+                    }
+                }
+
+                fn lowest_unset(&self) -> usize {
+                    match self {
+                        FixedBin::Fixed(bits) => bits.trailing_ones() as usize,
+                        FixedBin::Vec(bin) => bin.lowest_unset(),
+                    }
+                }
+
+                fn count_unset(&self) -> usize {
+                    match self {
+                        FixedBin::Fixed(bits) => bits.count_zeros() as usize,
+                        FixedBin::Vec(bin) => bin.count_unset(),
+                    }
+                }
+
+                fn find_first_unset_also_unset_in(&self, other: &Self) -> usize {
+                    match (self, other) {
+                        (FixedBin::Fixed(bits), FixedBin::Fixed(other)) => {
+                            (bits | other).trailing_ones() as usize
+                        }
+                        (FixedBin::Vec(bin), FixedBin::Vec(other)) => {
+                            bin.find_first_unset_also_unset_in(other)
+                        }
+                        _ => unreachable!("bitsets made for different nimbers"),
+                    }
+                }
+
+                fn copy_up_to_inclusive(&self, x: usize) -> Self {
+                    match self {
+                        FixedBin::Fixed(bits) => FixedBin::Fixed((!0 << x) | bits),
+                        FixedBin::Vec(bin) => FixedBin::Vec(bin.copy_up_to_inclusive(x)),
+                    }
+                }
+
+                fn union_with(&mut self, other: &Self) {
+                    match (self, other) {
+                        (FixedBin::Fixed(bits), FixedBin::Fixed(other)) => *bits |= other,
+                        (FixedBin::Vec(bin), FixedBin::Vec(other)) => bin.union_with(other),
+                        _ => unreachable!("bitsets made for different nimbers"),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_fixed_bin!(u32 => "u32", u64 => "u64", u128 => "u128");
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check<B: BitSet>() {
+        // 300 does not fit in any of the fixed width bitsets, which fall back to a bit vector
+        for largest in [3, 30, 300] {
+            let mut bin = B::make(largest);
+            assert_eq!(bin.lowest_unset(), 0, "for {}", B::NAME);
+            for x in 0..=largest {
+                bin.set_bit(x);
+            }
+            assert!(bin.get(largest), "for {}", B::NAME);
+            assert!(!bin.get(largest + 1), "for {}", B::NAME);
+            assert_eq!(bin.lowest_unset(), largest + 1, "for {}", B::NAME);
+
+            let mut other = B::make(largest);
+            assert_eq!(
+                bin.find_first_unset_also_unset_in(&other),
+                largest + 1,
+                "for {}",
+                B::NAME
+            );
+            other.set_bit(largest + 1);
+            assert_eq!(
+                bin.find_first_unset_also_unset_in(&other),
+                largest + 2,
+                "for {}",
+                B::NAME
+            );
+
+            let mut copy = bin.copy_up_to_inclusive(largest + 2);
+            assert_eq!(copy.lowest_unset(), largest + 1, "for {}", B::NAME);
+            assert_eq!(copy.count_unset(), 1, "for {}", B::NAME);
+            copy.set_bit(largest + 1);
+            assert_eq!(copy.count_unset(), 0, "for {}", B::NAME);
+
+            let mut union = B::make(largest);
+            union.set_bit(largest + 1);
+            union.union_with(&bin);
+            assert_eq!(union.lowest_unset(), largest + 2, "for {}", B::NAME);
+
+            bin.zero_bits();
+            assert_eq!(bin.lowest_unset(), 0, "for {}", B::NAME);
+        }
+    }
+
+    #[test]
+    fn test_backends() {
+        check::<VecBin>();
+        check::<FixedBin<u32>>();
+        check::<FixedBin<u64>>();
+        check::<FixedBin<u128>>();
+    }
+}
@@ -0,0 +1,314 @@
+use super::chunk::{self, write_atomically};
+use super::{BitSet, Game, GameCode, Level, Nimber, Stats};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The state of a game computed in tail mode, taken just before computing the value of heap n.
+///
+/// Together with the front (`front_<game>_<max_full_memory>`) and the tail ring
+/// (`nimbers_<game>_<n>`), which are stored as chunk files, this is everything needed to continue
+/// the computation from n exactly as if it was never interrupted, including the statistics
+/// gathered since the start.
+#[derive(Serialize, Deserialize)]
+// the bounds on N are implied by Nimber
+#[serde(bound = "")]
+pub struct Checkpoint<N: Nimber> {
+    pub code: String,
+    /// Width of the nimbers in bits.
+    pub width: u32,
+    pub n: usize,
+    pub max_full_memory: usize,
+    pub max_tail_memory: usize,
+    pub stats: Stats<N>,
+    /// Nimbers belonging to R, as generated by the last `Stats::gen_rares`.
+    pub r: Vec<usize>,
+    /// Heaps with a value in R, from the front and those found in tail mode.
+    pub rare: Vec<(usize, N)>,
+    /// The levels of the sparse space R was generated for, empty in checkpoints saved before
+    /// there was more than one level, which `restore` rejects.
+    #[serde(default)]
+    pub chain: Vec<Level>,
+    /// Heaps with a value which stops being common at each level after the first.
+    #[serde(default)]
+    pub levels: Vec<Vec<(usize, N)>>,
+}
+
+pub fn front_path(dir: &Path, code: &str, max_full_memory: usize) -> PathBuf {
+    dir.join(format!("front_{}_{}", code, max_full_memory))
+}
+
+pub fn nimbers_path(dir: &Path, code: &str, n: usize) -> PathBuf {
+    dir.join(format!("nimbers_{}_{}", code, n))
+}
+
+pub fn checkpoint_path(dir: &Path, code: &str, n: usize) -> PathBuf {
+    dir.join(format!("checkpoint_{}_{}.json", code, n))
+}
+
+/// Find the latest checkpoint of the game saved in dir.
+fn latest_path(dir: &Path, code: &GameCode) -> io::Result<Option<PathBuf>> {
+    let pattern = dir.join(format!("checkpoint_{}_*.json", code));
+
+    let mut last = None;
+    for path in glob::glob(&pattern.to_string_lossy())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+    {
+        let path = path.map_err(io::Error::from)?;
+        let n = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.rsplit_once('_'))
+            .and_then(|(_, n)| n.parse::<usize>().ok());
+
+        if let Some(n) = n {
+            last = std::cmp::max(last, Some(n));
+        }
+    }
+
+    Ok(last.map(|n| checkpoint_path(dir, &code.to_string(), n)))
+}
+
+/// Load the latest checkpoint of the game saved in dir, converting the nimbers to N if they were
+/// saved with a different width.
+pub fn latest<N: Nimber>(dir: &Path, code: &GameCode) -> io::Result<Option<Checkpoint<N>>> {
+    match latest_path(dir, code)? {
+        None => Ok(None),
+        Some(path) => {
+            let file = fs::File::open(path)?;
+            let checkpoint = serde_json::from_reader(io::BufReader::new(file))?;
+            Ok(Some(checkpoint))
+        }
+    }
+}
+
+/// The width of the nimbers (in bits) the latest checkpoint of the game was saved with.
+pub fn latest_width(dir: &Path, code: &GameCode) -> io::Result<Option<u32>> {
+    #[derive(Deserialize)]
+    struct Width {
+        width: u32,
+    }
+
+    match latest_path(dir, code)? {
+        None => Ok(None),
+        Some(path) => {
+            let file = fs::File::open(path)?;
+            let width: Width = serde_json::from_reader(io::BufReader::new(file))?;
+            Ok(Some(width.width))
+        }
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl<N: Nimber, B: BitSet> Game<N, B> {
+    pub fn checkpoint(&self, n: usize) -> Checkpoint<N> {
+        Checkpoint {
+            code: self.code.to_string(),
+            width: N::BITS,
+            n,
+            max_full_memory: self.nimbers.g.len(),
+            max_tail_memory: self.nimbers.g_back.len(),
+            stats: self.stats.clone(),
+            r: (0..self.stats.frequencies.len())
+                .filter(|&x| self.bits.rare.get(x))
+                .collect(),
+            rare: self.nimbers.rare.clone(),
+            chain: self.bits.chain.clone(),
+            levels: self.nimbers.levels.clone(),
+        }
+    }
+
+    /// Save the front of the sequence, which does not change in tail mode, so it is enough to
+    /// save it once, before the first checkpoint.
+    pub fn save_front(&self, dir: &Path) -> io::Result<()> {
+        let path = front_path(dir, &self.code.to_string(), self.nimbers.g.len());
+        chunk::write_storage(&path, &self.code.to_string(), 0, &self.nimbers.g)
+    }
+
+    /// Save the tail ring and the state of the game before computing the value of heap n.
+    ///
+    /// The state is written last, so a checkpoint file always has its ring next to it.
+    pub fn save_checkpoint(&self, dir: &Path, n: usize) -> io::Result<()> {
+        let code = self.code.to_string();
+        // g_back[i] is the value of the heap n - max_tail_memory + i
+        let first = n - self.nimbers.g_back.len();
+        chunk::write_storage(
+            &nimbers_path(dir, &code, n),
+            &code,
+            first,
+            &self.nimbers.g_back,
+        )?;
+
+        let json = serde_json::to_vec(&self.checkpoint(n))?;
+        write_atomically(&checkpoint_path(dir, &code, n), &json)
+    }
+
+    /// Restore the state saved by `save_front` and `save_checkpoint`, after which the
+    /// computation continues from heap `checkpoint.n`.
+    pub fn restore(&mut self, dir: &Path, checkpoint: Checkpoint<N>) -> io::Result<()> {
+        let code = self.code.to_string();
+        if checkpoint.code != code {
+            return Err(invalid_data(format!(
+                "checkpoint of game {} cannot be used for game {}",
+                checkpoint.code, code
+            )));
+        }
+
+        let max_full_memory = self.nimbers.g.len();
+        let max_tail_memory = self.nimbers.g_back.len();
+        if (checkpoint.max_full_memory, checkpoint.max_tail_memory)
+            != (max_full_memory, max_tail_memory)
+        {
+            return Err(invalid_data(format!(
+                "checkpoint was made with memory {} {}, not {} {}",
+                checkpoint.max_full_memory,
+                checkpoint.max_tail_memory,
+                max_full_memory,
+                max_tail_memory
+            )));
+        }
+
+        // the heaps of the levels after the first are not in older checkpoints, and only reading
+        // the whole sequence again would find them
+        if checkpoint.chain.is_empty() {
+            return Err(invalid_data(format!(
+                "checkpoint at {} predates the levels of the sparse space, remove the checkpoints \
+                 to resume from the saved nimbers",
+                checkpoint.n
+            )));
+        }
+
+        let front = front_path(dir, &code, max_full_memory);
+        self.nimbers
+            .load_g(chunk::read_expected(&front, &code, 0, max_full_memory)?);
+        let tail = nimbers_path(dir, &code, checkpoint.n);
+        let first = checkpoint.n - max_tail_memory;
+        self.nimbers
+            .load_g_back(chunk::read_expected(&tail, &code, first, max_tail_memory)?);
+        self.nimbers.rare = checkpoint.rare;
+
+        self.stats = checkpoint.stats;
+        self.bits.resize(self.stats.largest_nimber.to_usize());
+        self.bits.rare = B::make(self.stats.largest_nimber.to_usize());
+        for x in checkpoint.r {
+            self.bits.rare.set_bit(x);
+        }
+
+        self.bits.chain = checkpoint.chain;
+        self.nimbers.levels = checkpoint.levels;
+        self.stats.eliminated.resize(self.bits.chain.len(), 0);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::testing::{check_against_naive, TempDir};
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_and_restore() {
+        let dir = TempDir::new("checkpoint");
+
+        for (rules_str, levels) in [("0.156", 1), ("4.7", 1), ("0.104", 2)] {
+            let code: GameCode = rules_str.parse().unwrap();
+            let max_full_memory = 1024;
+            let max_tail_memory = max_full_memory;
+            let max = 4 * max_full_memory;
+            let saved = 3 * max_full_memory;
+
+            let g: Game<u16> =
+                check_against_naive(rules_str, max_full_memory, max, &dir, |g| g.levels = levels);
+
+            let mut resumed = Game::<u16>::new(code.clone(), max_full_memory, max_tail_memory);
+            resumed.levels = levels;
+            let checkpoint = latest(&dir, &code).unwrap().unwrap();
+            assert_eq!(checkpoint.n, saved);
+            resumed.restore(&dir, checkpoint).unwrap();
+            resumed.use_history(&dir);
+
+            for n in saved..max {
+                resumed.calc_rc_back(n).unwrap();
+            }
+
+            assert_eq!(resumed.nimbers.g, g.nimbers.g, "for game {}", rules_str);
+            assert_eq!(
+                resumed.nimbers.g_back, g.nimbers.g_back,
+                "for game {}",
+                rules_str
+            );
+            assert_eq!(
+                resumed.nimbers.rare, g.nimbers.rare,
+                "for game {}",
+                rules_str
+            );
+            assert_eq!(
+                resumed.nimbers.levels, g.nimbers.levels,
+                "for game {}",
+                rules_str
+            );
+            assert_eq!(resumed.bits.chain, g.bits.chain, "for game {}", rules_str);
+            assert_eq!(resumed.stats, g.stats, "for game {}", rules_str);
+            assert_eq!(
+                resumed.checkpoint(max).r,
+                g.checkpoint(max).r,
+                "for game {}",
+                rules_str
+            );
+
+            // nimbers saved as u16 are converted to u8 if they fit
+            assert_eq!(latest_width(&dir, &code).unwrap(), Some(16));
+            let mut narrower = Game::<u8>::new(code.clone(), max_full_memory, max_tail_memory);
+            narrower.levels = levels;
+            let checkpoint = latest(&dir, &code).unwrap().unwrap();
+            narrower.restore(&dir, checkpoint).unwrap();
+            narrower.use_history(&dir);
+            for n in saved..max {
+                narrower.calc_rc_back(n).unwrap();
+                assert_eq!(
+                    narrower.nimbers.last(n) as u16,
+                    g.nimbers.last(n),
+                    "for game {}",
+                    rules_str
+                );
+            }
+        }
+
+        let mut other = Game::<u16>::new("0.45".parse().unwrap(), 1024, 1024);
+        let checkpoint = latest::<u16>(&dir, &"4.7".parse().unwrap())
+            .unwrap()
+            .unwrap();
+        assert!(other.restore(&dir, checkpoint).is_err());
+
+        let mut smaller = Game::<u16>::new("4.7".parse().unwrap(), 1024, 512);
+        let checkpoint = latest::<u16>(&dir, &"4.7".parse().unwrap())
+            .unwrap()
+            .unwrap();
+        assert!(smaller.restore(&dir, checkpoint).is_err());
+
+        // checkpoints saved before the levels have no chain
+        let path = checkpoint_path(&dir, "4.7", 3 * 1024);
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        let fields = json.as_object_mut().unwrap();
+        fields.remove("chain");
+        fields.remove("levels");
+        fs::write(&path, serde_json::to_vec(&json).unwrap()).unwrap();
+        let mut old = Game::<u16>::new("4.7".parse().unwrap(), 1024, 1024);
+        let checkpoint = latest::<u16>(&dir, &"4.7".parse().unwrap())
+            .unwrap()
+            .unwrap();
+        let e = old.restore(&dir, checkpoint).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(e.to_string().contains("predates the levels"), "{}", e);
+
+        assert!(latest::<u16>(&dir, &"0.45".parse().unwrap())
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,535 @@
+use super::packed::Packed;
+use super::{Nimber, Storage};
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// Chunk files start with the magic bytes followed by the header, all numbers are big endian:
+///
+/// | field      | size          |                                                    |
+/// |------------|---------------|----------------------------------------------------|
+/// | magic      | 8             | `MAGIC`                                            |
+/// | version    | 2             | `VERSION`                                          |
+/// | width      | 1             | bytes per nimber (1, 2, 4, 8 or 16), 0 if packed   |
+/// | bits       | 1 if packed   | bits per packed nimber (1 to 128)                  |
+/// | code len   | 2             | length of the game code                            |
+/// | code       | code len      | the game code, as printed by `GameCode`            |
+/// | first      | 8             | heap size of the first nimber                      |
+/// | count      | 8             | number of nimbers                                  |
+/// | checksum   | 4             | CRC-32 of the header before it and all the nimbers |
+/// | nimbers    | width * count | G(first), G(first + 1), ...                        |
+///
+/// Packed nimbers are stored as the ceil(bits * count / 64) words of `Packed` instead, each of
+/// them a big endian u64.
+pub const MAGIC: [u8; 8] = *b"OCTALNIM";
+pub const VERSION: u16 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkHeader {
+    pub version: u16,
+    /// Bytes per nimber, 0 if they are packed.
+    pub width: usize,
+    /// Bits per nimber, 8 * width unless they are packed.
+    pub bits: u32,
+    pub code: String,
+    pub first: usize,
+    pub count: usize,
+    pub checksum: u32,
+}
+
+#[derive(Debug)]
+pub enum ChunkError {
+    Io(io::Error),
+    /// The file does not start with `MAGIC`, for example a headerless dump of an older version.
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnsupportedWidth(usize),
+    /// The file ends before the header or all the nimbers were read.
+    Truncated {
+        expected: usize,
+        found: usize,
+    },
+    ChecksumMismatch {
+        expected: u32,
+        found: u32,
+    },
+    /// The file holds nimbers of another game.
+    WrongGame {
+        expected: String,
+        found: String,
+    },
+    /// The file holds nimbers of other heap sizes.
+    WrongRange {
+        expected: (usize, usize),
+        found: (usize, usize),
+    },
+    /// The value of heap index does not fit into nimbers of the given width in bits.
+    ValueTooLarge {
+        index: usize,
+        value: u128,
+        bits: u32,
+    },
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChunkError::Io(e) => write!(f, "{}", e),
+            ChunkError::BadMagic => write!(f, "not a nimber chunk file"),
+            ChunkError::UnsupportedVersion(v) => write!(f, "unsupported chunk version {}", v),
+            ChunkError::UnsupportedWidth(w) => write!(f, "unsupported nimber width {} bytes", w),
+            ChunkError::Truncated { expected, found } => write!(
+                f,
+                "file truncated, expected {} bytes, found {}",
+                expected, found
+            ),
+            ChunkError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "checksum mismatch, expected {:08x}, found {:08x}",
+                expected, found
+            ),
+            ChunkError::WrongGame { expected, found } => {
+                write!(f, "expected nimbers of game {}, found {}", expected, found)
+            }
+            ChunkError::WrongRange { expected, found } => write!(
+                f,
+                "expected {} nimbers from {}, found {} from {}",
+                expected.1, expected.0, found.1, found.0
+            ),
+            ChunkError::ValueTooLarge { index, value, bits } => write!(
+                f,
+                "G({}) = {} does not fit into {} bits",
+                index, value, bits
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ChunkError {}
+
+impl From<io::Error> for ChunkError {
+    fn from(e: io::Error) -> Self {
+        ChunkError::Io(e)
+    }
+}
+
+impl From<ChunkError> for io::Error {
+    fn from(e: ChunkError) -> Self {
+        match e {
+            ChunkError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+fn checksum(header: &[u8], nimbers: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(header);
+    hasher.update(nimbers);
+    hasher.finalize()
+}
+
+/// Fill in the checksum of a chunk, which starts at `checksum_at`.
+fn seal(buf: &mut [u8], checksum_at: usize) {
+    let checksum = checksum(&buf[..checksum_at], &buf[checksum_at + 4..]);
+    buf[checksum_at..checksum_at + 4].copy_from_slice(&checksum.to_be_bytes());
+}
+
+fn encode_header(header: &ChunkHeader) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(29 + header.code.len());
+    buf.extend_from_slice(&MAGIC);
+    buf.extend_from_slice(&header.version.to_be_bytes());
+    buf.push(header.width as u8);
+    if header.width == 0 {
+        buf.push(header.bits as u8);
+    }
+    buf.extend_from_slice(&(header.code.len() as u16).to_be_bytes());
+    buf.extend_from_slice(header.code.as_bytes());
+    buf.extend_from_slice(&(header.first as u64).to_be_bytes());
+    buf.extend_from_slice(&(header.count as u64).to_be_bytes());
+    buf
+}
+
+/// Encode G(first), G(first + 1), ... of the game, together with the header.
+pub fn encode<N: Nimber>(code: &str, first: usize, nimbers: &[N]) -> Vec<u8> {
+    let width = (N::BITS / u8::BITS) as usize;
+    let header = ChunkHeader {
+        version: VERSION,
+        width,
+        bits: N::BITS,
+        code: code.to_string(),
+        first,
+        count: nimbers.len(),
+        checksum: 0,
+    };
+
+    let mut buf = encode_header(&header);
+    let checksum_at = buf.len();
+    buf.extend_from_slice(&[0; 4]);
+    buf.reserve(nimbers.len() * width);
+    for &nim in nimbers {
+        nim.write_be(&mut buf);
+    }
+    seal(&mut buf, checksum_at);
+
+    buf
+}
+
+/// Encode the packed G(first), G(first + 1), ... of the game, together with the header.
+pub fn encode_packed<N: Nimber>(code: &str, first: usize, nimbers: &Packed<N>) -> Vec<u8> {
+    let header = ChunkHeader {
+        version: VERSION,
+        width: 0,
+        bits: nimbers.bits(),
+        code: code.to_string(),
+        first,
+        count: nimbers.len(),
+        checksum: 0,
+    };
+
+    let mut buf = encode_header(&header);
+    let checksum_at = buf.len();
+    buf.extend_from_slice(&[0; 4]);
+    for &word in nimbers.words() {
+        buf.extend_from_slice(&word.to_be_bytes());
+    }
+    seal(&mut buf, checksum_at);
+
+    buf
+}
+
+struct Cursor<'a> {
+    buf: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ChunkError> {
+        // the length may come from a corrupt header
+        let end = self
+            .at
+            .checked_add(len)
+            .filter(|&end| end <= self.buf.len())
+            .ok_or(ChunkError::Truncated {
+                expected: self.at.saturating_add(len),
+                found: self.buf.len(),
+            })?;
+        self.at = end;
+        Ok(&self.buf[end - len..end])
+    }
+
+    fn take_u64(&mut self) -> Result<u64, ChunkError> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+/// Decode the header, without checking the checksum.
+fn decode_header(cursor: &mut Cursor) -> Result<ChunkHeader, ChunkError> {
+    if cursor.take(MAGIC.len()).map_err(|_| ChunkError::BadMagic)? != MAGIC {
+        return Err(ChunkError::BadMagic);
+    }
+
+    let version = u16::from_be_bytes(cursor.take(2)?.try_into().unwrap());
+    if version != VERSION {
+        return Err(ChunkError::UnsupportedVersion(version));
+    }
+
+    let width = cursor.take(1)?[0] as usize;
+    if ![0, 1, 2, 4, 8, 16].contains(&width) {
+        return Err(ChunkError::UnsupportedWidth(width));
+    }
+    let bits = if width == 0 {
+        let bits = cursor.take(1)?[0] as u32;
+        if !(1..=u128::BITS).contains(&bits) {
+            return Err(ChunkError::UnsupportedWidth(width));
+        }
+        bits
+    } else {
+        width as u32 * u8::BITS
+    };
+
+    let code_len = u16::from_be_bytes(cursor.take(2)?.try_into().unwrap()) as usize;
+    let code = String::from_utf8_lossy(cursor.take(code_len)?).into_owned();
+    let first = cursor.take_u64()? as usize;
+    let count = cursor.take_u64()? as usize;
+    let checksum = u32::from_be_bytes(cursor.take(4)?.try_into().unwrap());
+
+    Ok(ChunkHeader {
+        version,
+        width,
+        bits,
+        code,
+        first,
+        count,
+        checksum,
+    })
+}
+
+/// Decode the header and the nimbers, converting them to N if they were saved with a different
+/// width.
+pub fn decode<N: Nimber>(buf: &[u8]) -> Result<(ChunkHeader, Vec<N>), ChunkError> {
+    let mut cursor = Cursor { buf, at: 0 };
+    let header = decode_header(&mut cursor)?;
+    let checksum_at = cursor.at - 4;
+    let (first, count, width) = (header.first, header.count, header.width);
+    let len = if width == 0 {
+        count
+            .checked_mul(header.bits as usize)
+            .and_then(|bits| bits.div_ceil(64).checked_mul(8))
+    } else {
+        count.checked_mul(width)
+    };
+    let payload = cursor.take(len.ok_or(ChunkError::Truncated {
+        expected: usize::MAX,
+        found: buf.len(),
+    })?)?;
+
+    let found = checksum(&buf[..checksum_at], payload);
+    if found != header.checksum {
+        return Err(ChunkError::ChecksumMismatch {
+            expected: header.checksum,
+            found,
+        });
+    }
+
+    let values: Box<dyn Iterator<Item = u128>> = if width == 0 {
+        let words = payload
+            .chunks_exact(8)
+            .map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap()))
+            .collect();
+        let packed = Packed::<u128>::from_words(words, header.bits, count);
+        Box::new((0..count).map(move |i| packed.get(i)))
+    } else {
+        Box::new(
+            payload
+                .chunks_exact(width)
+                .map(|bytes| bytes.iter().fold(0u128, |v, &b| (v << 8) | b as u128)),
+        )
+    };
+
+    let mut nimbers = Vec::with_capacity(count);
+    for (i, value) in values.enumerate() {
+        let nim = N::try_from_u128(value).ok_or(ChunkError::ValueTooLarge {
+            index: first + i,
+            value,
+            bits: N::BITS,
+        })?;
+        nimbers.push(nim);
+    }
+
+    Ok((header, nimbers))
+}
+
+/// Write the file through a temporary one, so that a crash never leaves a partially written file
+/// under the final name.
+pub(crate) fn write_atomically(path: &Path, buf: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(buf)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+pub fn write<N: Nimber>(path: &Path, code: &str, first: usize, nimbers: &[N]) -> io::Result<()> {
+    write_atomically(path, &encode(code, first, nimbers))
+}
+
+/// Write the nimbers of the front or the tail ring, packed if they are packed in memory.
+pub fn write_storage<N: Nimber>(
+    path: &Path,
+    code: &str,
+    first: usize,
+    nimbers: &Storage<N>,
+) -> io::Result<()> {
+    match nimbers {
+        Storage::Packed(packed) => write_atomically(path, &encode_packed(code, first, packed)),
+        _ => write(path, code, first, nimbers.as_slice().unwrap()),
+    }
+}
+
+/// Read only the header of a chunk file.
+pub fn read_header(path: &Path) -> Result<ChunkHeader, ChunkError> {
+    let longest = 30 + u16::MAX as u64;
+    let mut buf = vec![];
+    fs::File::open(path)?.take(longest).read_to_end(&mut buf)?;
+    decode_header(&mut Cursor { buf: &buf, at: 0 })
+}
+
+pub fn read<N: Nimber>(path: &Path) -> Result<(ChunkHeader, Vec<N>), ChunkError> {
+    let mut buf = vec![];
+    fs::File::open(path)?.read_to_end(&mut buf)?;
+    decode(&buf)
+}
+
+/// Read G(first), ..., G(first + count - 1) of the game, checking that the file holds exactly
+/// these nimbers.
+pub fn read_expected<N: Nimber>(
+    path: &Path,
+    code: &str,
+    first: usize,
+    count: usize,
+) -> Result<Vec<N>, ChunkError> {
+    let (header, nimbers) = read(path)?;
+
+    if header.code != code {
+        return Err(ChunkError::WrongGame {
+            expected: code.to_string(),
+            found: header.code,
+        });
+    }
+    if (header.first, header.count) != (first, count) {
+        return Err(ChunkError::WrongRange {
+            expected: (first, count),
+            found: (header.first, header.count),
+        });
+    }
+
+    Ok(nimbers)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::testing::TempDir;
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let nimbers: Vec<u16> = vec![0, 1, 2, 0, 3, 1, 4, 7, 0];
+        let buf = encode("0.16", 1024, &nimbers);
+
+        let (header, decoded) = decode::<u16>(&buf).unwrap();
+        assert_eq!(decoded, nimbers);
+        assert_eq!(header.code, "0.16");
+        assert_eq!(header.first, 1024);
+        assert_eq!(header.count, nimbers.len());
+        assert_eq!(header.width, 2);
+    }
+
+    #[test]
+    fn test_convert_width() {
+        let mut nimbers: Vec<u32> = vec![0, 1, 200, 5];
+        let buf = encode("0.07", 0, &nimbers);
+
+        assert_eq!(decode::<u8>(&buf).unwrap().1, vec![0, 1, 200, 5]);
+        assert_eq!(decode::<u64>(&buf).unwrap().1, vec![0, 1, 200, 5]);
+
+        nimbers[3] = 300;
+        let buf = encode("0.07", 16, &nimbers);
+
+        assert_eq!(decode::<u16>(&buf).unwrap().1, vec![0, 1, 200, 300]);
+        assert!(matches!(
+            decode::<u8>(&buf),
+            Err(ChunkError::ValueTooLarge {
+                index: 19,
+                value: 300,
+                bits: 8
+            })
+        ));
+    }
+
+    #[test]
+    fn test_packed() {
+        let nimbers: Vec<u16> = (0..1000).map(|i| (i * 7 % 300) as u16).collect();
+        let packed = Packed::from_slice(&nimbers);
+        let buf = encode_packed("0.172", 64, &packed);
+
+        let (header, decoded) = decode::<u16>(&buf).unwrap();
+        assert_eq!(decoded, nimbers);
+        assert_eq!((header.width, header.bits), (0, 9));
+        assert_eq!((header.first, header.count), (64, nimbers.len()));
+        // 9 bits per nimber instead of 16
+        assert!(buf.len() < encode("0.172", 64, &nimbers).len() * 10 / 16);
+
+        assert_eq!(
+            decode::<u64>(&buf).unwrap().1,
+            nimbers.iter().map(|&x| x as u64).collect::<Vec<_>>()
+        );
+        assert!(matches!(
+            decode::<u8>(&buf),
+            Err(ChunkError::ValueTooLarge {
+                index: 101,
+                value: 259,
+                bits: 8
+            })
+        ));
+        assert!(matches!(
+            decode::<u16>(&buf[..buf.len() - 1]),
+            Err(ChunkError::Truncated { .. })
+        ));
+
+        let mut corrupted = buf.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 1;
+        assert!(matches!(
+            decode::<u16>(&corrupted),
+            Err(ChunkError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let nimbers: Vec<u16> = vec![0, 1, 2, 3];
+        let buf = encode("0.16", 0, &nimbers);
+
+        assert!(matches!(
+            decode::<u16>(&buf[4..]),
+            Err(ChunkError::BadMagic)
+        ));
+        assert!(matches!(
+            decode::<u16>(&buf[..buf.len() - 1]),
+            Err(ChunkError::Truncated { .. })
+        ));
+
+        let mut corrupted = buf.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 1;
+        assert!(matches!(
+            decode::<u16>(&corrupted),
+            Err(ChunkError::ChecksumMismatch { .. })
+        ));
+
+        let mut newer = buf.clone();
+        newer[MAGIC.len() + 1] += 1;
+        assert!(matches!(
+            decode::<u16>(&newer),
+            Err(ChunkError::UnsupportedVersion(2))
+        ));
+
+        // counts so large that the length of the nimbers overflows
+        let count_at = MAGIC.len() + 2 + 1 + 2 + "0.16".len() + 8;
+        let packed = encode_packed("0.16", 0, &Packed::from_slice(&nimbers));
+        for (buf, count_at) in [(buf, count_at), (packed, count_at + 1)] {
+            for count in [u64::MAX, u64::MAX / 2 + 1] {
+                let mut huge = buf.clone();
+                huge[count_at..count_at + 8].copy_from_slice(&count.to_be_bytes());
+                assert!(matches!(
+                    decode::<u16>(&huge),
+                    Err(ChunkError::Truncated { .. })
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_expected() {
+        let dir = TempDir::new("chunk");
+        let path = dir.join("nimbers_0.16_8");
+
+        let nimbers: Vec<u8> = vec![1, 2, 3, 4];
+        write(&path, "0.16", 4, &nimbers).unwrap();
+
+        assert_eq!(read_expected::<u8>(&path, "0.16", 4, 4).unwrap(), nimbers);
+        assert_eq!(read_header(&path).unwrap().width, 1);
+        assert!(matches!(
+            read_expected::<u8>(&path, "0.161", 4, 4),
+            Err(ChunkError::WrongGame { .. })
+        ));
+        assert!(matches!(
+            read_expected::<u8>(&path, "0.16", 0, 4),
+            Err(ChunkError::WrongRange { .. })
+        ));
+    }
+}
@@ -0,0 +1,353 @@
+use super::Rule;
+use std::fmt;
+use std::str::FromStr;
+
+/// A validated code of an octal (or hexadecimal) game, like "0.034", "4.07" or "0.B4".
+///
+/// The code consists of a single digit d0 (the moves which do not remove any tokens), a decimal
+/// point and the digits d1, d2, ... (the moves removing 1, 2, ... tokens).  Trailing zeros do not
+/// change the game, so they are removed, and "0.340" is the same code as "0.34".
+///
+/// Digits in parentheses at the end of the code are repeated forever, so "0.(3)" is nim (any
+/// number of tokens may be taken) and "0.1(37)" is 0.1373737...  Repeating codes are kept in the
+/// shortest form, so "0.3(33)" is the same code as "0.(3)".
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GameCode {
+    digits: Vec<u8>,
+    repeating: Vec<u8>,
+}
+
+/// The reason why a string is not a valid game code, together with the position (in characters)
+/// of the offending part of the string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseGameCodeError {
+    /// The string is empty.
+    Empty,
+    /// A character which is not a hexadecimal digit was found where a digit was expected.
+    InvalidDigit { position: usize, found: char },
+    /// The digit d0 is not followed by a decimal point.
+    MissingDecimalPoint { position: usize },
+    /// There are no digits after the decimal point or inside the parentheses.
+    MissingDigits { position: usize },
+    /// The parenthesis opened at the given position is never closed.
+    UnclosedParenthesis { position: usize },
+    /// There is something after the closing parenthesis.
+    TrailingCharacters { position: usize },
+    /// The digit d0 allows removing no tokens while leaving zero or one heaps, which is not a move.
+    InvalidD0 { position: usize, digit: u8 },
+}
+
+impl ParseGameCodeError {
+    pub fn position(&self) -> usize {
+        match *self {
+            ParseGameCodeError::Empty => 0,
+            ParseGameCodeError::InvalidDigit { position, .. }
+            | ParseGameCodeError::MissingDecimalPoint { position }
+            | ParseGameCodeError::MissingDigits { position }
+            | ParseGameCodeError::UnclosedParenthesis { position }
+            | ParseGameCodeError::TrailingCharacters { position }
+            | ParseGameCodeError::InvalidD0 { position, .. } => position,
+        }
+    }
+}
+
+impl fmt::Display for ParseGameCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseGameCodeError::Empty => write!(f, "empty game code"),
+            ParseGameCodeError::InvalidDigit { position, found } => {
+                write!(f, "invalid digit {:?} at position {}", found, position)
+            }
+            ParseGameCodeError::MissingDecimalPoint { position } => {
+                write!(f, "expected '.' at position {}", position)
+            }
+            ParseGameCodeError::MissingDigits { position } => {
+                write!(f, "expected digits at position {}", position)
+            }
+            ParseGameCodeError::UnclosedParenthesis { position } => {
+                write!(f, "parenthesis at position {} is never closed", position)
+            }
+            ParseGameCodeError::TrailingCharacters { position } => {
+                write!(
+                    f,
+                    "unexpected characters after ')' at position {}",
+                    position
+                )
+            }
+            ParseGameCodeError::InvalidD0 { position, digit } => write!(
+                f,
+                "digit {:X} at position {} allows only dividing moves (0, 4, 8 or C)",
+                digit, position
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseGameCodeError {}
+
+impl GameCode {
+    /// The digits d0, d1, ... of the code, without trailing zeros (or without the repeating
+    /// part).
+    pub fn digits(&self) -> &[u8] {
+        &self.digits
+    }
+
+    /// The digits repeated forever after `digits()`, empty if the code is finite.
+    pub fn repeating(&self) -> &[u8] {
+        &self.repeating
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.repeating.is_empty()
+    }
+
+    /// The rules of the game, i-th of them represents possible moves after removing i tokens.
+    ///
+    /// For a repeating code these are only the rules before the repeating part.
+    pub fn rules(&self) -> Vec<Rule> {
+        self.digits.iter().map(|&d| Rule::from_digit(d)).collect()
+    }
+
+    /// The rules of the repeating part of the code, so that the rule for removing i tokens is
+    /// `repeating_rules()[(i - rules().len()) % repeating_rules().len()]` for large enough i.
+    pub fn repeating_rules(&self) -> Vec<Rule> {
+        self.repeating
+            .iter()
+            .map(|&d| Rule::from_digit(d))
+            .collect()
+    }
+
+    fn normalise(&mut self) {
+        // the shortest period of the repeating digits
+        let len = self.repeating.len();
+        if let Some(period) = (1..len)
+            .find(|&p| len % p == 0 && (p..len).all(|i| self.repeating[i] == self.repeating[i - p]))
+        {
+            self.repeating.truncate(period);
+        }
+
+        if self.repeating.iter().all(|&d| d == 0) {
+            self.repeating.clear();
+        }
+
+        // 0.1(01) is the same as 0.(10)
+        while !self.repeating.is_empty()
+            && self.digits.len() > 1
+            && self.digits.last() == self.repeating.last()
+        {
+            self.digits.pop();
+            self.repeating.rotate_right(1);
+        }
+
+        while self.is_finite() && self.digits.len() > 2 && self.digits[self.digits.len() - 1] == 0 {
+            self.digits.pop();
+        }
+
+        if self.is_finite() && self.digits.len() == 1 {
+            // 0.(0) is 0.0
+            self.digits.push(0);
+        }
+    }
+}
+
+impl FromStr for GameCode {
+    type Err = ParseGameCodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars().enumerate();
+
+        let d0 = match chars.next() {
+            None => return Err(ParseGameCodeError::Empty),
+            Some((position, c)) => digit(position, c)?,
+        };
+
+        if d0 & 3 != 0 {
+            return Err(ParseGameCodeError::InvalidD0 {
+                position: 0,
+                digit: d0,
+            });
+        }
+
+        match chars.next() {
+            Some((_, '.')) => {}
+            _ => return Err(ParseGameCodeError::MissingDecimalPoint { position: 1 }),
+        }
+
+        let mut code = GameCode {
+            digits: vec![d0],
+            repeating: vec![],
+        };
+
+        let mut open = None;
+        let mut closed = false;
+        for (position, c) in chars {
+            if closed {
+                return Err(ParseGameCodeError::TrailingCharacters { position });
+            } else if c == '(' && open.is_none() {
+                open = Some(position);
+            } else if c == ')' && open.is_some() {
+                closed = true;
+            } else if open.is_some() {
+                code.repeating.push(digit(position, c)?);
+            } else {
+                code.digits.push(digit(position, c)?);
+            }
+        }
+
+        match open {
+            None if code.digits.len() == 1 => {
+                return Err(ParseGameCodeError::MissingDigits { position: 2 });
+            }
+            Some(position) if !closed => {
+                return Err(ParseGameCodeError::UnclosedParenthesis { position });
+            }
+            Some(position) if code.repeating.is_empty() => {
+                return Err(ParseGameCodeError::MissingDigits {
+                    position: position + 1,
+                });
+            }
+            _ => {}
+        }
+
+        code.normalise();
+        Ok(code)
+    }
+}
+
+fn digit(position: usize, c: char) -> Result<u8, ParseGameCodeError> {
+    c.to_digit(16)
+        .map(|d| d as u8)
+        .ok_or(ParseGameCodeError::InvalidDigit { position, found: c })
+}
+
+impl fmt::Display for GameCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:X}.", self.digits[0])?;
+        for d in &self.digits[1..] {
+            write!(f, "{:X}", d)?;
+        }
+        if !self.is_finite() {
+            write!(f, "(")?;
+            for d in &self.repeating {
+                write!(f, "{:X}", d)?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        for (s, normalised) in [
+            ("0.034", "0.034"),
+            ("0.340", "0.34"),
+            ("0.000", "0.0"),
+            ("4.07", "4.07"),
+            ("4.0", "4.0"),
+            ("0.b4", "0.B4"),
+            ("8.F", "8.F"),
+            ("0.(3)", "0.(3)"),
+            ("0.3(3)", "0.(3)"),
+            ("0.33(33)", "0.(3)"),
+            ("0.1(37)", "0.1(37)"),
+            ("0.1(01)", "0.(10)"),
+            ("0.12(0)", "0.12"),
+            ("0.(0)", "0.0"),
+            ("4.(c)", "4.(C)"),
+        ] {
+            let code: GameCode = s.parse().unwrap();
+            assert_eq!(code.to_string(), normalised);
+            assert_eq!(code, normalised.parse().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_rules() {
+        let code: GameCode = "0.0340".parse().unwrap();
+        assert_eq!(code.rules(), super::super::rules_from_str("0.034"));
+    }
+
+    #[test]
+    fn test_repeating() {
+        let code: GameCode = "0.1(37)".parse().unwrap();
+        assert!(!code.is_finite());
+        assert_eq!(code.digits(), &[0, 1]);
+        assert_eq!(code.repeating(), &[3, 7]);
+        assert_eq!(code.repeating_rules(), super::super::rules_from_str("37"));
+
+        let code: GameCode = "0.(3)".parse().unwrap();
+        assert_eq!(code.rules(), super::super::rules_from_str("0"));
+    }
+
+    #[test]
+    fn test_errors() {
+        for (s, err) in [
+            ("", ParseGameCodeError::Empty),
+            (
+                "abc",
+                ParseGameCodeError::InvalidD0 {
+                    position: 0,
+                    digit: 0xA,
+                },
+            ),
+            (
+                "0.0x4",
+                ParseGameCodeError::InvalidDigit {
+                    position: 3,
+                    found: 'x',
+                },
+            ),
+            (
+                "x.04",
+                ParseGameCodeError::InvalidDigit {
+                    position: 0,
+                    found: 'x',
+                },
+            ),
+            (
+                "034",
+                ParseGameCodeError::MissingDecimalPoint { position: 1 },
+            ),
+            ("4.", ParseGameCodeError::MissingDigits { position: 2 }),
+            (
+                "0.3.4",
+                ParseGameCodeError::InvalidDigit {
+                    position: 3,
+                    found: '.',
+                },
+            ),
+            (
+                "3.07",
+                ParseGameCodeError::InvalidD0 {
+                    position: 0,
+                    digit: 3,
+                },
+            ),
+            (
+                "0.1(3",
+                ParseGameCodeError::UnclosedParenthesis { position: 3 },
+            ),
+            ("0.1()", ParseGameCodeError::MissingDigits { position: 4 }),
+            (
+                "0.(3)1",
+                ParseGameCodeError::TrailingCharacters { position: 5 },
+            ),
+            (
+                "0.(3(4))",
+                ParseGameCodeError::InvalidDigit {
+                    position: 4,
+                    found: '(',
+                },
+            ),
+        ] {
+            let res: Result<GameCode, _> = s.parse();
+            assert_eq!(res, Err(err.clone()), "for {:?}", s);
+            assert_eq!(res.unwrap_err().position(), err.position());
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use super::checkpoint::nimbers_path;
+use super::chunk::{self, ChunkError};
+use super::store::NimberStore;
+use super::Nimber;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Number of rings kept in memory by `History`, unless set with `with_capacity`.
+///
+/// Each of the rare heaps found in tail mode and the full proofs read the sequence in order, so
+/// a few rings are enough to read every saved ring only once.
+pub const CACHED: usize = 8;
+
+/// The nimbers computed in tail mode, read back from the rings saved with the checkpoints.
+///
+/// In tail mode the values of the heaps between the front and the tail ring are no longer in
+/// memory.  They are needed only in the rare cases the sparse space is not enough to compute a
+/// value: a rare value found past the front, whose successors are in the middle of the sequence.
+pub struct History<N: Nimber> {
+    dir: PathBuf,
+    code: String,
+    max_tail_memory: usize,
+    /// Number of rings kept in memory.
+    capacity: usize,
+    /// Rings (by the heap after their last) in the order they were used, the latest last.
+    cached: Mutex<VecDeque<(usize, Vec<N>)>>,
+}
+
+impl<N: Nimber> History<N> {
+    pub fn new(dir: PathBuf, code: String, max_tail_memory: usize) -> Self {
+        Self {
+            dir,
+            code,
+            max_tail_memory,
+            capacity: CACHED,
+            cached: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// The same history, keeping the given number of rings in memory (at least one).
+    pub fn with_capacity(mut self, rings: usize) -> Self {
+        self.capacity = std::cmp::max(rings, 1);
+        self
+    }
+
+    /// The same history, for nimbers of another width.
+    pub fn widen<M: Nimber>(self) -> History<M> {
+        History::new(self.dir, self.code, self.max_tail_memory).with_capacity(self.capacity)
+    }
+
+    /// The value of the heap m, which has to be in one of the saved rings.
+    pub fn read(&self, m: usize) -> Result<N, ChunkError> {
+        // the ring saved at n holds the heaps n - max_tail_memory..n
+        let end = (m / self.max_tail_memory + 1) * self.max_tail_memory;
+        let i = m % self.max_tail_memory;
+
+        let mut cached = self.cached.lock().unwrap();
+        if let Some(pos) = cached.iter().position(|(e, _)| *e == end) {
+            let ring = cached.remove(pos).unwrap();
+            let value = ring.1[i];
+            cached.push_back(ring);
+            return Ok(value);
+        }
+
+        let path = nimbers_path(&self.dir, &self.code, end);
+        let first = end - self.max_tail_memory;
+        let ring = chunk::read_expected(&path, &self.code, first, self.max_tail_memory)?;
+        let value = ring[i];
+        if cached.len() == self.capacity {
+            cached.pop_front();
+        }
+        cached.push_back((end, ring));
+
+        Ok(value)
+    }
+}
+
+// the rings are read only when needed, so every heap is taken as held, and reading one which was
+// not saved is an error
+impl<N: Nimber> NimberStore<N> for History<N> {
+    fn held(&self, _m: usize) -> Range<usize> {
+        0..usize::MAX
+    }
+
+    fn get(&self, m: usize) -> N {
+        self.read(m)
+            .unwrap_or_else(|e| panic!("unable to read G({}): {}", m, e))
+    }
+}
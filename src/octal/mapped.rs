@@ -0,0 +1,382 @@
+use super::{BitSet, Game, Nimber, Overflow, Storage};
+use memmap2::MmapMut;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+
+/// Mapped files start with a header, padded to `HEADER` bytes, all its numbers are big endian:
+///
+/// | field      | size     |                                                 |
+/// |------------|----------|-------------------------------------------------|
+/// | magic      | 8        | `MAGIC`                                         |
+/// | version    | 2        | `VERSION`                                       |
+/// | width      | 1        | bytes per nimber (1, 2, 4, 8 or 16)             |
+/// | code len   | 2        | length of the game code                         |
+/// | code       | code len | the game code, as printed by `GameCode`         |
+/// | len        | 8        | number of nimbers, max_full_memory              |
+/// | computed   | 8        | G(0), ..., G(computed - 1) were saved by `sync` |
+///
+/// The nimbers G(0), G(1), ... follow the header in the byte order of the machine, so that they
+/// can be used in place.
+pub const MAGIC: [u8; 8] = *b"OCTALMAP";
+pub const VERSION: u16 = 1;
+/// The nimbers start at a page boundary, which is aligned for all the widths.
+const HEADER: usize = 4096;
+
+/// Number of heaps computed between two `sync`s of the front while computing it.
+pub const SYNC_EVERY: usize = 1 << 24;
+
+pub fn mapped_path(dir: &Path, code: &str, max_full_memory: usize) -> PathBuf {
+    dir.join(format!("mapped_{}_{}", code, max_full_memory))
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+struct Header {
+    width: usize,
+    code: String,
+    len: usize,
+}
+
+impl Header {
+    /// Where `computed` is stored.
+    fn computed_at(&self) -> usize {
+        8 + 2 + 1 + 2 + self.code.len() + 8
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.computed_at() + 8);
+        buf.extend_from_slice(&MAGIC);
+        buf.extend_from_slice(&VERSION.to_be_bytes());
+        buf.push(self.width as u8);
+        buf.extend_from_slice(&(self.code.len() as u16).to_be_bytes());
+        buf.extend_from_slice(self.code.as_bytes());
+        buf.extend_from_slice(&(self.len as u64).to_be_bytes());
+        buf.extend_from_slice(&0u64.to_be_bytes());
+        buf
+    }
+
+    /// Decode the header and the number of nimbers computed.
+    fn decode(buf: &[u8]) -> io::Result<(Header, usize)> {
+        let take = |at: usize, len: usize| {
+            buf.get(at..at + len)
+                .ok_or_else(|| invalid_data("mapped file truncated".to_string()))
+        };
+
+        if take(0, 8)? != MAGIC {
+            return Err(invalid_data("not a mapped nimber file".to_string()));
+        }
+        let version = u16::from_be_bytes(take(8, 2)?.try_into().unwrap());
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported mapped file version {}",
+                version
+            )));
+        }
+        let width = take(10, 1)?[0] as usize;
+        let code_len = u16::from_be_bytes(take(11, 2)?.try_into().unwrap()) as usize;
+        let code = String::from_utf8_lossy(take(13, code_len)?).into_owned();
+        let len = u64::from_be_bytes(take(13 + code_len, 8)?.try_into().unwrap()) as usize;
+        let computed = u64::from_be_bytes(take(21 + code_len, 8)?.try_into().unwrap()) as usize;
+
+        Ok((Header { width, code, len }, computed))
+    }
+}
+
+/// The width (in bits) of the nimbers in the mapped file at path, if there is one.
+pub fn width(path: &Path) -> io::Result<Option<u32>> {
+    match fs::File::open(path) {
+        Ok(file) => {
+            let mut buf = vec![];
+            file.take(HEADER as u64).read_to_end(&mut buf)?;
+            let (header, _) = Header::decode(&buf)?;
+            Ok(Some(header.width as u32 * u8::BITS))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The front of the sequence in a file mapped into memory, which is paged in and out by the OS,
+/// so the front is not limited by RAM, and which keeps the nimbers computed after a crash.
+pub struct MappedFront<N: Nimber> {
+    map: MmapMut,
+    path: PathBuf,
+    header: Header,
+    nimbers: PhantomData<N>,
+}
+
+impl<N: Nimber> MappedFront<N> {
+    const WIDTH: usize = std::mem::size_of::<N>();
+
+    /// Create the file at path for len nimbers, all of them N::MAX, like an unmapped front.
+    pub fn create(path: &Path, code: &str, len: usize) -> io::Result<Self> {
+        let header = Header {
+            width: Self::WIDTH,
+            code: code.to_string(),
+            len,
+        };
+        if header.computed_at() + 8 > HEADER {
+            return Err(invalid_data(format!("game code {} is too long", code)));
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len((HEADER + len * Self::WIDTH) as u64)?;
+        // SAFETY: the file is not changed by anything else while it is mapped
+        let map = unsafe { MmapMut::map_mut(&file)? };
+
+        let mut front = Self {
+            map,
+            path: path.to_path_buf(),
+            header,
+            nimbers: PhantomData,
+        };
+        let encoded = front.header.encode();
+        front.map[..encoded.len()].copy_from_slice(&encoded);
+        front.fill(N::MAX);
+        front.map.flush()?;
+
+        Ok(front)
+    }
+
+    /// Map the file created for the game at path, and return it with the number of heaps it
+    /// holds the values of.
+    pub fn open(path: &Path, code: &str, len: usize) -> io::Result<(Self, usize)> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        // SAFETY: as in `create`
+        let map = unsafe { MmapMut::map_mut(&file)? };
+
+        let (header, computed) = Header::decode(&map[..std::cmp::min(map.len(), HEADER)])?;
+        if header.code != code {
+            return Err(invalid_data(format!(
+                "mapped file of game {} cannot be used for game {}",
+                header.code, code
+            )));
+        }
+        if header.width != Self::WIDTH {
+            return Err(invalid_data(format!(
+                "mapped file has {} bit nimbers, not {}",
+                header.width as u32 * u8::BITS,
+                N::BITS
+            )));
+        }
+        if header.len != len || map.len() != HEADER + len * Self::WIDTH {
+            return Err(invalid_data(format!(
+                "mapped file holds {} nimbers, not {}",
+                header.len, len
+            )));
+        }
+
+        let front = Self {
+            map,
+            path: path.to_path_buf(),
+            header,
+            nimbers: PhantomData,
+        };
+        Ok((front, computed))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write the nimbers to the disk, and record that the first `computed` of them are final.
+    ///
+    /// The nimbers are flushed before the header, so after a crash the file has at least the
+    /// nimbers it claims to have.
+    pub fn sync(&mut self, computed: usize) -> io::Result<()> {
+        self.map.flush_range(HEADER, self.map.len() - HEADER)?;
+        let at = self.header.computed_at();
+        self.map[at..at + 8].copy_from_slice(&(computed as u64).to_be_bytes());
+        self.map.flush_range(0, HEADER)
+    }
+
+    /// Convert the nimbers to a wider type, rewriting the file in place of this one.
+    pub fn widen<M: Nimber>(self) -> io::Result<MappedFront<M>> {
+        let at = self.header.computed_at();
+        let computed = u64::from_be_bytes(self.map[at..at + 8].try_into().unwrap()) as usize;
+
+        let mut tmp = self.path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let mut wider = MappedFront::<M>::create(&tmp, &self.header.code, self.header.len)?;
+        for (w, &x) in wider.iter_mut().zip(self.iter()) {
+            *w = M::from_usize(x.to_usize());
+        }
+        wider.sync(computed)?;
+
+        // the mapping stays valid after the file is renamed
+        fs::rename(&tmp, &self.path)?;
+        wider.path = self.path.clone();
+        Ok(wider)
+    }
+}
+
+impl<N: Nimber> Deref for MappedFront<N> {
+    type Target = [N];
+
+    fn deref(&self) -> &[N] {
+        // SAFETY: the nimbers start at a page boundary, so they are aligned, the file was
+        // created with room for header.len of them, and any bytes are a valid unsigned integer
+        unsafe {
+            std::slice::from_raw_parts(self.map.as_ptr().add(HEADER) as *const N, self.header.len)
+        }
+    }
+}
+
+impl<N: Nimber> DerefMut for MappedFront<N> {
+    fn deref_mut(&mut self) -> &mut [N] {
+        // SAFETY: as in `deref`
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.map.as_mut_ptr().add(HEADER) as *mut N,
+                self.header.len,
+            )
+        }
+    }
+}
+
+impl<N: Nimber, B: BitSet> Game<N, B> {
+    /// Keep the front in the file at path, which is created unless it exists, and return the
+    /// number of heaps it already holds the values of, to be `replay`ed after `init`.
+    pub fn map_front(&mut self, path: &Path) -> io::Result<usize> {
+        let code = self.code.to_string();
+        let len = self.nimbers.g.len();
+        let (front, computed) = if path.exists() {
+            MappedFront::open(path, &code, len)?
+        } else {
+            (MappedFront::create(path, &code, len)?, 0)
+        };
+        self.nimbers.set_front(Storage::Mapped(front));
+        Ok(computed)
+    }
+
+    /// Rebuild the state of the game after `init` from the values of the heaps up to end, which
+    /// are already in the front, as if they were computed one by one.
+    ///
+    /// The statistics of the proofs (prev_values and the values eliminated by each level) are
+    /// not restored, they only cover the heaps computed from now on.
+    pub fn replay(&mut self, end: usize) -> Result<(), Overflow> {
+        for n in self.rules.len()..end {
+            let nim = self.nimbers.g.get(n).to_usize();
+            self.set_next_g_n(n, nim)?;
+        }
+        Ok(())
+    }
+
+    /// Record that the values of the heaps before n are final, see `Storage::sync`.
+    pub fn sync_front(&mut self, n: usize) -> io::Result<()> {
+        self.nimbers.g.sync(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::testing::{front, TempDir};
+    use super::*;
+
+    #[test]
+    fn test_mapped_front() {
+        let dir = TempDir::new("mapped");
+
+        for (rules_str, levels) in [("0.156", 1), ("0.034", 1), ("0.104", 2)] {
+            let max = 2000;
+            let stopped = 1234;
+            let path = mapped_path(&dir, rules_str, max);
+
+            let memory: Game<u16> = front(rules_str, max, |g| g.levels = levels);
+
+            let mut mapped = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
+            mapped.levels = levels;
+            assert_eq!(mapped.map_front(&path).unwrap(), 0);
+            mapped.init();
+            for n in mapped.rules.len()..stopped {
+                mapped.calc_rc(n).unwrap();
+            }
+            mapped.sync_front(stopped).unwrap();
+            drop(mapped);
+
+            // the run is resumed from the file, as after a crash
+            let mut resumed = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
+            resumed.levels = levels;
+            assert_eq!(resumed.map_front(&path).unwrap(), stopped);
+            resumed.init();
+            resumed.replay(stopped).unwrap();
+            for n in stopped..max {
+                resumed.calc_rc(n).unwrap();
+            }
+
+            assert_eq!(
+                resumed.nimbers.g, memory.nimbers.g,
+                "for game {}",
+                rules_str
+            );
+            assert_eq!(
+                resumed.nimbers.rare, memory.nimbers.rare,
+                "for game {}",
+                rules_str
+            );
+            assert_eq!(
+                resumed.nimbers.levels, memory.nimbers.levels,
+                "for game {}",
+                rules_str
+            );
+            assert_eq!(
+                resumed.bits.chain, memory.bits.chain,
+                "for game {}",
+                rules_str
+            );
+            assert_eq!(
+                resumed.stats.frequencies, memory.stats.frequencies,
+                "for game {}",
+                rules_str
+            );
+            assert_eq!(
+                resumed.check_period(max),
+                memory.check_period(max),
+                "for game {}",
+                rules_str
+            );
+
+            // a file is only used for the game and the front it was created for
+            assert!(Game::<u16>::new("0.45".parse().unwrap(), max, 0)
+                .map_front(&path)
+                .is_err());
+            assert!(Game::<u16>::new(rules_str.parse().unwrap(), max / 2, 0)
+                .map_front(&path)
+                .is_err());
+            assert!(Game::<u8>::new(rules_str.parse().unwrap(), max, 0)
+                .map_front(&path)
+                .is_err());
+        }
+
+        // a wider front replaces the file, with the same nimbers
+        let path = mapped_path(&dir, "0.7", 100);
+        let mut narrow = MappedFront::<u8>::create(&path, "0.7", 100).unwrap();
+        for (m, x) in narrow.iter_mut().enumerate() {
+            *x = (m % 3) as u8;
+        }
+        narrow.sync(100).unwrap();
+        assert_eq!(width(&path).unwrap(), Some(8));
+        let wide = Storage::Mapped(narrow).widen::<u32>();
+        assert_eq!(width(&path).unwrap(), Some(32));
+        let (reopened, computed) = MappedFront::<u32>::open(&path, "0.7", 100).unwrap();
+        assert_eq!(computed, 100);
+        assert_eq!(wide, *reopened);
+        assert!(reopened
+            .iter()
+            .enumerate()
+            .all(|(m, &x)| x == (m % 3) as u32));
+
+        assert_eq!(width(&dir.join("missing")).unwrap(), None);
+    }
+}
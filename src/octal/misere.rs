@@ -0,0 +1,797 @@
+use super::{rule_at, GameCode, Rule};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+/// A position of misère play, the sizes of its heaps in nondecreasing order.  Heaps without moves
+/// are left out: adding one to a position changes neither its moves nor its outcome.
+type Position = Vec<usize>;
+
+/// States `verify` visits at most before giving up.
+pub const MAX_STATES: usize = 1 << 22;
+
+/// Misère play of an octal game: the misère quotients of the positions with heaps up to n,
+/// computed one heap size after another, in the style of Plambeck and Siegel.
+///
+/// The quotient identifies the positions G and H if G + X and H + X have the same misère outcome
+/// for every position X.  It is a commutative monoid (adding positions), with its P-portion (the
+/// elements of the P-positions) and the pretending function (the element of each heap).
+///
+/// For every n a candidate is read off the outcomes of positions: starting from the empty
+/// position, the positions which add one heap to the ones found so far are new elements unless
+/// they have the same outcomes as an element found before, together with each test position.
+/// Two elements of the quotient differ with some element added, so the positions of the elements
+/// found are tests, until there are no new ones.  The candidate is the quotient only if it is a
+/// monoid and `verify` confirms the outcome of every position, otherwise all the positions of up
+/// to `depth` heaps become tests as well, with depth growing by a heap.
+pub struct Misere {
+    rules: Vec<Rule>,
+    repeating: Vec<Rule>,
+    /// Largest number of elements of the quotient looked for.
+    max_order: usize,
+    /// Largest number of heaps of the test positions.
+    max_depth: usize,
+    /// options[m] are the positions a heap of size m moves to, options[0] is empty.
+    options: Vec<Vec<Position>>,
+    /// Misère outcomes of the positions with the heap n seen so far, true for P-positions.
+    outcomes: HashMap<Position, bool>,
+    /// The positions added to the candidates to tell them apart.
+    tests: Vec<Position>,
+    tested: HashSet<Position>,
+    /// Number of heaps of the positions which were all made tests, which only grows with n.
+    depth: usize,
+    quotient: MisereQuotient,
+}
+
+/// The misère quotient of the positions with heaps up to some n.  The elements are numbered in
+/// the order they were found, the identity (the empty position) first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MisereQuotient {
+    /// mul[x][y] is the product of x and y.
+    mul: Vec<Vec<usize>>,
+    /// Is the element in the P-portion?
+    p: Vec<bool>,
+    /// pretend[m] is the element of the heap m, pretend[0] is the identity.
+    pretend: Vec<usize>,
+}
+
+/// A presentation of the quotient by generators and relations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Presentation {
+    /// The smallest heap pretending to be each generator, a, b, c, ...
+    pub generators: Vec<usize>,
+    /// The normal form of every element, as the exponents of the generators: the fewest
+    /// generators with the most a's, then the most b's, and so on.
+    pub normal: Vec<Vec<u32>>,
+    /// The words which are not normal forms, while all their divisors are, with the normal forms
+    /// equal to them.  Rewriting them gives the normal form of every word.
+    pub relations: Vec<(Vec<u32>, Vec<u32>)>,
+}
+
+#[derive(Debug)]
+pub enum MisereError {
+    /// The quotient of the heaps up to `heaps` has more than max_order elements.
+    Order { heaps: usize, max_order: usize },
+    /// Test positions of up to max_depth heaps did not give the quotient of the heaps up to
+    /// `heaps`, which may be larger than max_order as well.
+    Depth { heaps: usize, max_depth: usize },
+    /// `verify` gave up after `MAX_STATES` states.
+    States { heaps: usize },
+}
+
+impl fmt::Display for MisereError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MisereError::Order { heaps, max_order } => write!(
+                f,
+                "the quotient of the heaps up to {} has more than {} elements",
+                heaps, max_order
+            ),
+            MisereError::Depth { heaps, max_depth } => write!(
+                f,
+                "test positions of {} heaps are not enough for the quotient of the heaps up to {}",
+                max_depth, heaps
+            ),
+            MisereError::States { heaps } => write!(
+                f,
+                "unable to verify the quotient of the heaps up to {} within {} states",
+                heaps, MAX_STATES
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MisereError {}
+
+impl Misere {
+    pub fn new(code: &GameCode, max_order: usize, max_depth: usize) -> Self {
+        Self {
+            rules: code.rules(),
+            repeating: code.repeating_rules(),
+            max_order,
+            max_depth,
+            options: vec![vec![]],
+            outcomes: HashMap::new(),
+            tests: vec![vec![]],
+            tested: HashSet::from([vec![]]),
+            depth: 0,
+            quotient: MisereQuotient {
+                mul: vec![vec![0]],
+                p: vec![false],
+                pretend: vec![0],
+            },
+        }
+    }
+
+    /// The quotient of the heaps up to n, after the heaps up to n - 1.
+    pub fn calc_quotient(&mut self, n: usize) -> Result<&MisereQuotient, MisereError> {
+        assert_eq!(
+            n,
+            self.options.len(),
+            "the heaps are added one after another"
+        );
+        let options = self.heap_options(n);
+        self.options.push(options);
+
+        let heaps: Vec<usize> = (1..=n).filter(|&m| !self.options[m].is_empty()).collect();
+        loop {
+            if let Some(quotient) = self.candidate(n, &heaps)? {
+                // which covers all the outcomes computed so far
+                self.quotient = quotient;
+                self.outcomes.clear();
+                return Ok(&self.quotient);
+            }
+            // the positions with the new heaps first, then with one more heap
+            while !self.add_tests(multisets(&heaps, self.depth)) {
+                if self.depth == self.max_depth {
+                    return Err(MisereError::Depth {
+                        heaps: n,
+                        max_depth: self.max_depth,
+                    });
+                }
+                self.depth += 1;
+            }
+        }
+    }
+
+    /// Add the positions which are not tests yet to the tests, and tell if there were any.
+    fn add_tests(&mut self, positions: Vec<Position>) -> bool {
+        let count = self.tests.len();
+        for position in positions {
+            if self.tested.insert(position.clone()) {
+                self.tests.push(position);
+            }
+        }
+        self.tests.len() > count
+    }
+
+    /// The last quotient computed.
+    pub fn quotient(&self) -> &MisereQuotient {
+        &self.quotient
+    }
+
+    /// Is the position (sorted, without heaps with no moves) a misère P-position?
+    pub fn outcome(&mut self, position: &[usize]) -> bool {
+        // the last quotient tells the outcomes of the positions of the heaps it covers
+        if position
+            .last()
+            .is_none_or(|&m| m < self.quotient.pretend.len())
+        {
+            return self.quotient.is_p(self.quotient.element(position));
+        }
+        if let Some(&p) = self.outcomes.get(position) {
+            return p;
+        }
+
+        // the player to move wins when there are no moves, and a position with a heap has one
+        let mut p = !position.is_empty();
+        'moves: for k in 0..position.len() {
+            if k > 0 && position[k - 1] == position[k] {
+                continue;
+            }
+            let mut rest = position.to_vec();
+            let m = rest.remove(k);
+            for o in 0..self.options[m].len() {
+                let option = merge(&rest, &self.options[m][o]);
+                if self.outcome(&option) {
+                    p = false;
+                    break 'moves;
+                }
+            }
+        }
+
+        self.outcomes.insert(position.to_vec(), p);
+        p
+    }
+
+    /// The positions a heap of size m moves to, without the heaps with no moves.
+    fn heap_options(&self, m: usize) -> Vec<Position> {
+        let live = |heaps: &[usize]| -> Position {
+            let mut position: Position = heaps
+                .iter()
+                .copied()
+                .filter(|&h| !self.options[h].is_empty())
+                .collect();
+            position.sort_unstable();
+            position
+        };
+
+        let mut options = vec![];
+        for i in 0..=m {
+            let rule = rule_at(&self.rules, &self.repeating, i);
+            let left = m - i;
+
+            if rule.all && i > 0 && left == 0 {
+                options.push(vec![]);
+            }
+            if rule.some && i > 0 && left > 0 {
+                options.push(live(&[left]));
+            }
+            if rule.divide {
+                for a in 1..=left / 2 {
+                    options.push(live(&[a, left - a]));
+                }
+            }
+            if rule.divide3 {
+                for a in 1..=left / 3 {
+                    for b in a..=(left - a) / 2 {
+                        options.push(live(&[a, b, left - a - b]));
+                    }
+                }
+            }
+        }
+
+        options.sort_unstable();
+        options.dedup();
+        options
+    }
+
+    /// The outcomes of the position with each of the tests added.
+    fn row(&mut self, position: &[usize], tests: &[Position]) -> Vec<bool> {
+        tests
+            .iter()
+            .map(|t| self.outcome(&merge(position, t)))
+            .collect()
+    }
+
+    /// The candidate for the quotient of the heaps up to n (those with moves are `heaps`), if it
+    /// is the quotient.
+    fn candidate(
+        &mut self,
+        n: usize,
+        heaps: &[usize],
+    ) -> Result<Option<MisereQuotient>, MisereError> {
+        let (positions, next) = loop {
+            let (positions, next) = self.elements(n, heaps)?;
+            if !self.add_tests(positions.clone()) {
+                break (positions, next);
+            }
+        };
+        let order = positions.len();
+        let add = |x: usize, position: &[usize]| position.iter().fold(x, |x, &m| next[x][m]);
+
+        // the heaps are added to the elements in any order, and the element of a position alone
+        // tells what adding it does, so the elements form a monoid
+        for x in 0..order {
+            for (j, &a) in heaps.iter().enumerate() {
+                for &b in &heaps[j + 1..] {
+                    if next[next[x][a]][b] != next[next[x][b]][a] {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+        for y in 0..order {
+            for &m in heaps {
+                let position = merge(&positions[y], &[m]);
+                if (0..order).any(|x| add(x, &position) != add(x, &positions[next[y][m]])) {
+                    return Ok(None);
+                }
+            }
+        }
+
+        let quotient = MisereQuotient {
+            mul: (0..order)
+                .map(|x| positions.iter().map(|y| add(x, y)).collect())
+                .collect(),
+            p: positions
+                .iter()
+                .map(|position| self.outcome(position))
+                .collect(),
+            pretend: next[0].clone(),
+        };
+        let images: Vec<Vec<usize>> = self
+            .options
+            .iter()
+            .map(|options| {
+                let mut images: Vec<usize> = options.iter().map(|o| add(0, o)).collect();
+                images.sort_unstable();
+                images.dedup();
+                images
+            })
+            .collect();
+
+        if verify(&quotient, heaps, &images).ok_or(MisereError::States { heaps: n })? {
+            Ok(Some(quotient))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The elements of the candidate with the current tests by their first positions, which
+    /// have the fewest heaps, and next[x][m], the element of the position of x with the heap m
+    /// added (x itself for heaps without moves).
+    #[allow(clippy::type_complexity)]
+    fn elements(
+        &mut self,
+        n: usize,
+        heaps: &[usize],
+    ) -> Result<(Vec<Position>, Vec<Vec<usize>>), MisereError> {
+        let mut positions: Vec<Position> = vec![vec![]];
+        let tests = self.tests.clone();
+        let mut rows = HashMap::from([(self.row(&[], &tests), 0)]);
+        let mut next: Vec<Vec<usize>> = vec![];
+        let mut x = 0;
+        while x < positions.len() {
+            let mut row_next: Vec<usize> = vec![x; n + 1];
+            for &m in heaps {
+                let position = merge(&positions[x], &[m]);
+                let row = self.row(&position, &tests);
+                row_next[m] = match rows.get(&row) {
+                    Some(&y) => y,
+                    None => {
+                        if positions.len() == self.max_order {
+                            return Err(MisereError::Order {
+                                heaps: n,
+                                max_order: self.max_order,
+                            });
+                        }
+                        rows.insert(row, positions.len());
+                        positions.push(position);
+                        positions.len() - 1
+                    }
+                };
+            }
+            next.push(row_next);
+            x += 1;
+        }
+
+        Ok((positions, next))
+    }
+}
+
+/// What `verify` keeps of a position G: its element x, whether it has a heap, and the set W of
+/// the elements z such that G + Z has a move to a P-position in a heap of G, for any Z with the
+/// element z, as a bitset.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct State {
+    x: usize,
+    heaps: bool,
+    wins: Vec<u64>,
+}
+
+/// Does the element of every position tell its outcome?  Then positions with the same element
+/// are identified by the quotient, as their outcomes with any position added are the same.
+///
+/// A position G is a P-position if it has a move and none of its moves lead to a P-position,
+/// that is if it has a heap and the identity is not in W.  Adding a heap H with the element y to
+/// G keeps the moves in the heaps of G, which now win with z if they won with yz, and adds the
+/// moves in H to its options O, which win with z if x z x(O) is in the P-portion.  So the states
+/// of all positions, built by adding heaps one by one, are found from the states of smaller ones,
+/// and there are finitely many of them, so checking every state checks every position.  Returns
+/// None after visiting `MAX_STATES` states.
+fn verify(quotient: &MisereQuotient, heaps: &[usize], images: &[Vec<usize>]) -> Option<bool> {
+    let (mul, p) = (&quotient.mul, &quotient.p);
+    let order = quotient.order();
+    let words = order.div_ceil(64);
+
+    // heaps with the same element and the same elements of options are added the same way
+    let mut kinds: Vec<(usize, &[usize])> = heaps
+        .iter()
+        .map(|&m| (quotient.pretend[m], &images[m][..]))
+        .collect();
+    kinds.sort_unstable();
+    kinds.dedup();
+
+    // the elements z with a winning move in the heap of a kind, with the rest of G being x
+    let mut moves: HashMap<(usize, usize), Vec<u64>> = HashMap::new();
+
+    let start = State {
+        x: 0,
+        heaps: false,
+        wins: vec![0; words],
+    };
+    let mut seen = HashSet::from([start.clone()]);
+    let mut queue = VecDeque::from([start]);
+
+    while let Some(state) = queue.pop_front() {
+        let wins = state.wins[0] & 1 != 0;
+        if p[state.x] != (state.heaps && !wins) {
+            return Some(false);
+        }
+
+        for (k, &(y, to)) in kinds.iter().enumerate() {
+            let new = moves.entry((state.x, k)).or_insert_with(|| {
+                let mut new = vec![0; words];
+                for z in 0..order {
+                    if to.iter().any(|&o| p[mul[mul[state.x][z]][o]]) {
+                        new[z / 64] |= 1 << (z % 64);
+                    }
+                }
+                new
+            });
+            let mut wins = new.clone();
+            for z in 0..order {
+                let yz = mul[y][z];
+                if state.wins[yz / 64] & (1 << (yz % 64)) != 0 {
+                    wins[z / 64] |= 1 << (z % 64);
+                }
+            }
+
+            let added = State {
+                x: mul[state.x][y],
+                heaps: true,
+                wins,
+            };
+            if seen.insert(added.clone()) {
+                if seen.len() > MAX_STATES {
+                    return None;
+                }
+                queue.push_back(added);
+            }
+        }
+    }
+
+    Some(true)
+}
+
+/// The position with the heaps of both.
+fn merge(a: &[usize], b: &[usize]) -> Position {
+    let mut position = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] <= b[j] {
+            position.push(a[i]);
+            i += 1;
+        } else {
+            position.push(b[j]);
+            j += 1;
+        }
+    }
+    position.extend_from_slice(&a[i..]);
+    position.extend_from_slice(&b[j..]);
+    position
+}
+
+/// All the positions of at most `depth` of the heaps.
+fn multisets(heaps: &[usize], depth: usize) -> Vec<Position> {
+    let mut all = vec![vec![]];
+    // the positions of the last size, with the index of their largest heap
+    let mut last: Vec<(Position, usize)> = vec![(vec![], 0)];
+    for _ in 0..depth {
+        let mut longer = vec![];
+        for (position, first) in &last {
+            for (j, &m) in heaps.iter().enumerate().skip(*first) {
+                let mut position = position.clone();
+                position.push(m);
+                longer.push((position, j));
+            }
+        }
+        all.extend(longer.iter().map(|(position, _)| position.clone()));
+        last = longer;
+    }
+    all
+}
+
+impl MisereQuotient {
+    /// The number of elements.
+    pub fn order(&self) -> usize {
+        self.p.len()
+    }
+
+    /// The product of the elements x and y.
+    pub fn mul(&self, x: usize, y: usize) -> usize {
+        self.mul[x][y]
+    }
+
+    /// Is the element in the P-portion?
+    pub fn is_p(&self, x: usize) -> bool {
+        self.p[x]
+    }
+
+    /// The element of the heap m.
+    pub fn pretend(&self, m: usize) -> usize {
+        self.pretend[m]
+    }
+
+    /// The normal forms of the elements of the P-portion, in the order of the normal forms.
+    pub fn p_portion(&self, presentation: &Presentation) -> Vec<String> {
+        let mut p: Vec<&Vec<u32>> = (0..self.order())
+            .filter(|&x| self.p[x])
+            .map(|x| &presentation.normal[x])
+            .collect();
+        p.sort_by_key(|&w| (w.iter().sum::<u32>(), std::cmp::Reverse(w)));
+        p.into_iter().map(|w| Presentation::word(w)).collect()
+    }
+
+    /// The element of a position given by the sizes of its heaps.
+    pub fn element(&self, heaps: &[usize]) -> usize {
+        heaps.iter().fold(0, |x, &m| self.mul[x][self.pretend[m]])
+    }
+
+    /// The generators are the elements of the heaps which are not products of the elements of
+    /// smaller heaps, so each is named after the heap.
+    pub fn presentation(&self) -> Presentation {
+        let order = self.order();
+        let mut generators = vec![];
+        let mut elements: Vec<usize> = vec![];
+        let mut generated = vec![false; order];
+        generated[0] = true;
+        for m in 1..self.pretend.len() {
+            let g = self.pretend[m];
+            if generated[g] {
+                continue;
+            }
+            generators.push(m);
+            elements.push(g);
+            let mut stack: Vec<usize> = (0..order).filter(|&x| generated[x]).collect();
+            while let Some(x) = stack.pop() {
+                for &g in &elements {
+                    let y = self.mul[x][g];
+                    if !generated[y] {
+                        generated[y] = true;
+                        stack.push(y);
+                    }
+                }
+            }
+        }
+
+        // the words by the number of generators, the most a's first and so on, each a divisor
+        // of a normal form times a generator
+        let k = generators.len();
+        let value = |word: &[u32]| {
+            word.iter()
+                .zip(&elements)
+                .fold(0, |x, (&e, &g)| (0..e).fold(x, |x, _| self.mul[x][g]))
+        };
+        let mut normal: Vec<Option<Vec<u32>>> = vec![None; order];
+        normal[0] = Some(vec![0; k]);
+        let mut normals = HashSet::from([vec![0; k]]);
+        let mut relations = vec![];
+        let mut level = vec![vec![0; k]];
+        while !level.is_empty() {
+            let mut words: Vec<Vec<u32>> = level
+                .iter()
+                .flat_map(|v| {
+                    (0..k).map(move |i| {
+                        let mut w = v.clone();
+                        w[i] += 1;
+                        w
+                    })
+                })
+                .collect();
+            words.sort_unstable_by(|a, b| b.cmp(a));
+            words.dedup();
+
+            level.clear();
+            for w in words {
+                let x = value(&w);
+                match &normal[x] {
+                    None => {
+                        normal[x] = Some(w.clone());
+                        normals.insert(w.clone());
+                        level.push(w);
+                    }
+                    Some(form) => {
+                        let minimal = (0..k).all(|i| {
+                            let mut divisor = w.clone();
+                            w[i] == 0 || {
+                                divisor[i] -= 1;
+                                normals.contains(&divisor)
+                            }
+                        });
+                        if minimal {
+                            relations.push((w, form.clone()));
+                        }
+                    }
+                }
+            }
+        }
+
+        Presentation {
+            generators,
+            normal: normal.into_iter().map(|form| form.unwrap()).collect(),
+            relations,
+        }
+    }
+}
+
+impl Presentation {
+    /// A word in the generators a, b, c, ..., like "a^2bc" or "1" for the identity.
+    pub fn word(exponents: &[u32]) -> String {
+        let mut word = String::new();
+        for (i, &e) in exponents.iter().enumerate() {
+            if e == 0 {
+                continue;
+            }
+            if i < 26 {
+                word.push((b'a' + i as u8) as char);
+            } else {
+                word.push_str(&format!("x{}", i));
+            }
+            if e > 1 {
+                word.push_str(&format!("^{}", e));
+            }
+        }
+        if word.is_empty() {
+            word.push('1');
+        }
+        word
+    }
+}
+
+impl fmt::Display for Presentation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let generators: Vec<String> = (0..self.generators.len())
+            .map(|i| {
+                let mut e = vec![0; self.generators.len()];
+                e[i] = 1;
+                Presentation::word(&e)
+            })
+            .collect();
+        let relations: Vec<String> = self
+            .relations
+            .iter()
+            .map(|(w, form)| format!("{} = {}", Presentation::word(w), Presentation::word(form)))
+            .collect();
+        write!(f, "<{} | {}>", generators.join(", "), relations.join(", "))
+    }
+}
+
+// the presentation, the P-portion and the pretending function
+impl fmt::Display for MisereQuotient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let presentation = self.presentation();
+        let word = |x: usize| Presentation::word(&presentation.normal[x]);
+
+        writeln!(f, "order: {}", self.order())?;
+        writeln!(f, "quotient: {}", presentation)?;
+        writeln!(
+            f,
+            "P-portion: {{{}}}",
+            self.p_portion(&presentation).join(", ")
+        )?;
+        let pretend: Vec<String> = (1..self.pretend.len())
+            .map(|m| format!("{}: {}", m, word(self.pretend[m])))
+            .collect();
+        write!(f, "pretending function: {}", pretend.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn quotient(code: &str, heaps: usize) -> MisereQuotient {
+        let mut misere = Misere::new(&code.parse().unwrap(), 1000, 4);
+        for n in 1..=heaps {
+            misere.calc_quotient(n).unwrap();
+        }
+        misere.quotient().clone()
+    }
+
+    /// The misère outcome of a position read off the game tree alone, true for P-positions,
+    /// keeping the heaps with no moves as well.
+    fn brute_force(
+        rules: &[Rule],
+        repeating: &[Rule],
+        position: &[usize],
+        memo: &mut HashMap<Position, bool>,
+    ) -> bool {
+        if let Some(&p) = memo.get(position) {
+            return p;
+        }
+
+        let mut options: Vec<Position> = vec![];
+        for k in 0..position.len() {
+            let m = position[k];
+            let mut rest = position.to_vec();
+            rest.remove(k);
+            for i in 0..=m {
+                let rule = rule_at(rules, repeating, i);
+                let left = m - i;
+                let mut heaps: Vec<Vec<usize>> = vec![];
+                if rule.all && i > 0 && left == 0 {
+                    heaps.push(vec![]);
+                }
+                if rule.some && i > 0 && left > 0 {
+                    heaps.push(vec![left]);
+                }
+                if rule.divide {
+                    heaps.extend((1..=left / 2).map(|a| vec![a, left - a]));
+                }
+                if rule.divide3 {
+                    for a in 1..=left / 3 {
+                        heaps.extend((a..=(left - a) / 2).map(|b| vec![a, b, left - a - b]));
+                    }
+                }
+                for h in heaps {
+                    let mut option = rest.clone();
+                    option.extend(h);
+                    option.sort_unstable();
+                    options.push(option);
+                }
+            }
+        }
+
+        // the player to move wins when there are no moves
+        let p = !options.is_empty()
+            && options
+                .iter()
+                .all(|option| !brute_force(rules, repeating, option, memo));
+        memo.insert(position.to_vec(), p);
+        p
+    }
+
+    #[test]
+    fn test_misere_quotients() {
+        for (code, heaps, order, presentation, p) in [
+            // a single token is taken, so only the parity of the tokens matters
+            ("0.3", 6, 2, "<a | a^2 = 1>", &["a"][..]),
+            // misère nim with heaps of 1 and 2 tokens
+            ("0.(3)", 2, 6, "<a, b | a^2 = 1, b^3 = b>", &["a", "b^2"]),
+            // R8 of Plambeck and Siegel
+            (
+                "0.75",
+                10,
+                8,
+                "<a, b, c | a^2 = 1, bc = ab, c^2 = b^2, b^3 = b>",
+                &["a", "b^2"],
+            ),
+        ] {
+            let q = quotient(code, heaps);
+            assert_eq!(q.order(), order, "for game {}", code);
+            let presentation_found = q.presentation();
+            assert_eq!(
+                presentation_found.to_string(),
+                presentation,
+                "for game {}",
+                code
+            );
+            assert_eq!(q.p_portion(&presentation_found), p, "for game {}", code);
+        }
+    }
+
+    #[test]
+    fn test_misere_outcomes() {
+        for (code, heaps) in [
+            ("0.3", 8),
+            ("0.(3)", 3),
+            ("0.75", 10),
+            ("0.17", 8),
+            ("4.7", 6),
+        ] {
+            let code: GameCode = code.parse().unwrap();
+            let mut misere = Misere::new(&code, 1000, 4);
+            for n in 1..=heaps {
+                misere.calc_quotient(n).unwrap();
+            }
+            let q = misere.quotient().clone();
+            let live: Vec<usize> = (1..=heaps)
+                .filter(|&m| !misere.options[m].is_empty())
+                .collect();
+
+            // positions of more heaps than the tests had
+            let (rules, repeating) = (code.rules(), code.repeating_rules());
+            let mut memo = HashMap::new();
+            for position in multisets(&live, 5) {
+                assert_eq!(
+                    q.is_p(q.element(&position)),
+                    brute_force(&rules, &repeating, &position, &mut memo),
+                    "for game {} at {:?}",
+                    code,
+                    position
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use super::simd::Scan;
+use super::{max_heaps, Game, GameCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::{self, Debug, Display};
+use std::hash::Hash;
+use std::ops::BitXor;
+
+/// An unsigned integer type used to store the nimbers of a game.
+///
+/// Narrower types allow keeping more of the sequence in memory, so the width should be chosen
+/// as small as possible, but large enough for all the values of the game.
+pub trait Nimber:
+    Copy
+    + Ord
+    + Hash
+    + Default
+    + Debug
+    + Display
+    + Send
+    + Sync
+    + BitXor<Output = Self>
+    + Scan
+    + Serialize
+    + DeserializeOwned
+    + 'static
+{
+    const BITS: u32;
+    const MIN: Self;
+    const MAX: Self;
+
+    /// The next wider type the nimbers are promoted to when a value does not fit (u128 for u128).
+    type Wider: Nimber;
+
+    /// Convert from usize, truncating like `as` does.
+    fn from_usize(x: usize) -> Self;
+    fn try_from_usize(x: usize) -> Option<Self>;
+    fn to_usize(self) -> usize;
+    fn try_from_u128(x: u128) -> Option<Self>;
+    /// Convert from u128, truncating like `as` does.
+    fn from_u128(x: u128) -> Self;
+    fn to_u128(self) -> u128;
+    /// Append the big endian bytes of self to buf.
+    fn write_be(self, buf: &mut Vec<u8>);
+}
+
+macro_rules! impl_nimber {
+    ($($t:ty => $wider:ty),*) => {
+        $(
+            impl Nimber for $t {
+                const BITS: u32 = <$t>::BITS;
+                const MIN: Self = <$t>::MIN;
+                const MAX: Self = <$t>::MAX;
+
+                type Wider = $wider;
+
+                #[inline(always)]
+                fn from_usize(x: usize) -> Self {
+                    x as $t
+                }
+
+                fn try_from_usize(x: usize) -> Option<Self> {
+                    <$t>::try_from(x).ok()
+                }
+
+                #[inline(always)]
+                fn to_usize(self) -> usize {
+                    self as usize
+                }
+
+                fn try_from_u128(x: u128) -> Option<Self> {
+                    <$t>::try_from(x).ok()
+                }
+
+                #[inline(always)]
+                fn from_u128(x: u128) -> Self {
+                    x as $t
+                }
+
+                #[inline(always)]
+                fn to_u128(self) -> u128 {
+                    self as u128
+                }
+
+                fn write_be(self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_be_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_nimber!(u8 => u16, u16 => u32, u32 => u64, u64 => u128, u128 => u128);
+
+/// Widths (in bits) of the supported nimber types.
+pub const WIDTHS: [u32; 5] = [8, 16, 32, 64, 128];
+
+/// The value of heap n does not fit in the nimbers of the given width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overflow {
+    pub n: usize,
+    pub nim: usize,
+    pub bits: u32,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "G({}) = {} does not fit in {} bits",
+            self.n, self.nim, self.bits
+        )
+    }
+}
+
+impl std::error::Error for Overflow {}
+
+/// Number of heaps computed to guess the width of the nimbers of a game.
+const PROBE: usize = 1 << 14;
+
+/// The width of the nimbers of games which are not probed.
+const DEFAULT_WIDTH: u32 = 16;
+
+/// Guess the smallest width (in bits) sufficient for the nimbers of the game, computing no more
+/// heaps than the front of the run (max_full_memory).
+///
+/// The values of octal games grow very slowly (those of 0.167 do not exceed 64 for heaps up to
+/// 2^40), so compute the first few heaps and leave room for values 4 times larger than the
+/// largest of them.  The values of games with a repeating part may grow linearly, and those of
+/// hexadecimal games are slow to compute, so they start with 16 bits and are promoted when a
+/// value does not fit.
+pub fn guess_width(code: &GameCode, max_full_memory: usize) -> u32 {
+    let rules = code.rules();
+    if !code.repeating_rules().is_empty() || max_heaps(&rules) > 2 {
+        return DEFAULT_WIDTH;
+    }
+
+    let probe = PROBE.min(max_full_memory).max(rules.len());
+    let mut g = Game::<u32>::new(code.clone(), probe, 0);
+    g.verbose = false;
+    g.init();
+    for n in g.rules.len()..probe {
+        // the values of PROBE heaps are far below 2^32
+        g.calc_rc(n).unwrap();
+    }
+
+    width_for(g.stats.largest_nimber as u128)
+}
+
+/// The smallest width leaving room for values 4 times larger than largest.
+fn width_for(largest: u128) -> u32 {
+    let needed = 4 * (largest + 1);
+    *WIDTHS
+        .iter()
+        .find(|&&bits| bits == 128 || needed <= 1 << bits)
+        .unwrap_or(&128)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_guess_width() {
+        // the values of 0.07 (dawson's kayles) are at most 9
+        assert_eq!(guess_width(&"0.07".parse().unwrap(), 1 << 20), 8);
+        assert_eq!(guess_width(&"0.07".parse().unwrap(), 4), 8);
+        assert_eq!(guess_width(&"0.B4".parse().unwrap(), 1 << 20), 16);
+        assert_eq!(guess_width(&"0.(4)".parse().unwrap(), 1 << 20), 16);
+
+        assert_eq!(width_for(0), 8);
+        assert_eq!(width_for(63), 8);
+        assert_eq!(width_for(64), 16);
+        assert_eq!(width_for(16383), 16);
+        assert_eq!(width_for(16384), 32);
+        assert_eq!(width_for(u64::MAX as u128), 128);
+    }
+}
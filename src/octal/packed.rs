@@ -0,0 +1,189 @@
+use super::Nimber;
+use std::marker::PhantomData;
+
+/// Number of bits needed for x, at least 1.
+pub fn bits_for(x: u128) -> u32 {
+    std::cmp::max(1, u128::BITS - x.leading_zeros())
+}
+
+fn mask(bits: u32) -> u128 {
+    if bits == u128::BITS {
+        u128::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+/// Nimbers stored with as many bits each as the largest of them needs, whatever the width of N.
+///
+/// The values of most games need a few bits more than a power of two (9 bits for 0.014 and
+/// 0.172), so they take almost half of the memory of the next wider type.  The nimbers are
+/// repacked with more bits when a value does not fit.
+///
+/// Nimber m takes the bits m * bits..(m + 1) * bits of the words, from the least significant.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Packed<N: Nimber> {
+    words: Vec<u64>,
+    bits: u32,
+    len: usize,
+    nimbers: PhantomData<N>,
+}
+
+impl<N: Nimber> Packed<N> {
+    /// Number of words of len nimbers, with two more so that `get` can always read three words.
+    fn words_for(len: usize, bits: u32) -> usize {
+        (len * bits as usize).div_ceil(64) + 2
+    }
+
+    /// len nimbers, all 0, with the given bits each.
+    pub fn new(len: usize, bits: u32) -> Self {
+        Self {
+            words: vec![0; Self::words_for(len, bits)],
+            bits,
+            len,
+            nimbers: PhantomData,
+        }
+    }
+
+    pub fn from_slice(g: &[N]) -> Self {
+        let largest = g.iter().max().map_or(0, |x| x.to_u128());
+        let mut packed = Self::new(g.len(), bits_for(largest));
+        for (m, &x) in g.iter().enumerate() {
+            packed.set(m, x);
+        }
+        packed
+    }
+
+    /// The nimbers stored in words, as returned by `words`.
+    pub fn from_words(mut words: Vec<u64>, bits: u32, len: usize) -> Self {
+        words.resize(Self::words_for(len, bits), 0);
+        Self {
+            words,
+            bits,
+            len,
+            nimbers: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bits per nimber.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// The words holding the nimbers, without the ones after the last nimber.
+    pub fn words(&self) -> &[u64] {
+        &self.words[..self.words.len() - 2]
+    }
+
+    #[inline]
+    pub fn get(&self, m: usize) -> N {
+        let bit = m * self.bits as usize;
+        let (w, off) = (bit / 64, bit % 64);
+        let mut x = (self.words[w] as u128 | (self.words[w + 1] as u128) << 64) >> off;
+        if off + self.bits as usize > 128 {
+            x |= (self.words[w + 2] as u128) << (128 - off);
+        }
+        N::from_u128(x & mask(self.bits))
+    }
+
+    /// Set nimber m to x, repacking all of them with more bits first if x needs them.
+    pub fn set(&mut self, m: usize, x: N) {
+        let x = x.to_u128();
+        if bits_for(x) > self.bits {
+            self.repack(bits_for(x));
+        }
+
+        let bit = m * self.bits as usize;
+        let (w, off) = (bit / 64, bit % 64);
+        let mask = mask(self.bits);
+        let low = self.words[w] as u128 | (self.words[w + 1] as u128) << 64;
+        let low = low & !(mask << off) | x << off;
+        self.words[w] = low as u64;
+        self.words[w + 1] = (low >> 64) as u64;
+        if off + self.bits as usize > 128 {
+            // the rest of x is in the third word
+            let shift = 128 - off;
+            self.words[w + 2] = self.words[w + 2] & !((mask >> shift) as u64) | (x >> shift) as u64;
+        }
+    }
+
+    /// Store the nimbers with the given bits each, which have to be enough for all of them.
+    pub fn repack(&mut self, bits: u32) {
+        let mut packed = Self::new(self.len, bits);
+        for m in 0..self.len {
+            packed.set(m, self.get(m));
+        }
+        *self = packed;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = N> + '_ {
+        (0..self.len).map(|m| self.get(m))
+    }
+
+    /// The same nimbers, read as another type.
+    pub fn widen<M: Nimber>(self) -> Packed<M> {
+        Packed {
+            words: self.words,
+            bits: self.bits,
+            len: self.len,
+            nimbers: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_packed() {
+        assert_eq!(bits_for(0), 1);
+        assert_eq!(bits_for(1), 1);
+        assert_eq!(bits_for(2), 2);
+        assert_eq!(bits_for(255), 8);
+        assert_eq!(bits_for(256), 9);
+        assert_eq!(bits_for(u128::MAX), 128);
+
+        // values growing over the word boundaries, repacked with every next power of two
+        let values: Vec<u128> = (0..300)
+            .map(|m| (m * m * 2654435761) % (1 << (m / 3)))
+            .collect();
+        let mut packed = Packed::<u128>::new(values.len(), 1);
+        for (m, &x) in values.iter().enumerate() {
+            packed.set(m, x);
+            assert_eq!(packed.bits(), bits_for(*values[..=m].iter().max().unwrap()));
+            assert!(values[..=m]
+                .iter()
+                .enumerate()
+                .all(|(k, &x)| packed.get(k) == x));
+        }
+        assert!(packed.iter().eq(values.iter().copied()));
+
+        // overwriting keeps the neighbours
+        for m in 0..values.len() {
+            packed.set(m, 0);
+            assert_eq!(packed.get(m), 0);
+            if m + 1 < values.len() {
+                assert_eq!(packed.get(m + 1), values[m + 1]);
+            }
+        }
+
+        let g: Vec<u16> = (0..1000).map(|m| (m % 300) as u16).collect();
+        let packed = Packed::from_slice(&g);
+        assert_eq!(packed.bits(), 9);
+        assert!(packed.words().len() * 64 < g.len() * 10);
+        assert!(packed.iter().eq(g.iter().copied()));
+        let copy = Packed::<u16>::from_words(packed.words().to_vec(), 9, g.len());
+        assert!(copy == packed);
+        let wide = packed.widen::<u64>();
+        assert!(wide.iter().eq(g.iter().map(|&x| x as u64)));
+    }
+}
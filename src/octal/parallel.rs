@@ -0,0 +1,228 @@
+use super::{BitSet, Game, Nimber, NimberStore, Rule};
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// When `rc` splits the work on a single heap between threads.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thresholds {
+    /// Number of pairs `prove` checks on its own before it starts the other threads.
+    ///
+    /// Most proofs end long before that, and starting the threads would take longer than them.
+    pub serial_pairs: usize,
+    /// Number of pairs each thread checks in the first round of a parallel proof, doubled in each
+    /// next round, so a proof ending early does not wait for the threads to check the whole
+    /// range.
+    pub first_block: usize,
+    /// Smallest number of heaps of a level of the sparse space paired with the others in
+    /// parallel.
+    pub parallel_heaps: usize,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            serial_pairs: 1 << 16,
+            first_block: 1 << 14,
+            parallel_heaps: 1 << 12,
+        }
+    }
+}
+
+/// Number of pairs a thread checks between looking if the values it looks for were found by the
+/// threads before it.
+const CHECK_EVERY: usize = 1 << 10;
+
+/// Set the first pair j in chunk (pairs (j, m - j)) with each of the values looked for, which are
+/// given by their indices in `index` (usize::MAX for the others), unless first already has one.
+///
+/// `found[k]` is the first chunk which has the value k, and the thread stops as soon as all the
+/// values are in this chunk or the ones before it, as the later pairs cannot change the result.
+/// Returns false if it stopped for this reason.
+fn first_pairs<N: Nimber>(
+    g: &impl NimberStore<N>,
+    m: usize,
+    pairs: Range<usize>,
+    chunk: usize,
+    index: &[usize],
+    found: &[AtomicUsize],
+    first: &mut [Option<usize>],
+) -> bool {
+    for (checked, j) in pairs.enumerate() {
+        if checked % CHECK_EVERY == 0 && found.iter().all(|f| f.load(Ordering::Relaxed) <= chunk) {
+            return false;
+        }
+
+        let loc = (g.get(j) ^ g.get(m - j)).to_usize();
+        if loc < index.len() && index[loc] != usize::MAX && first[index[loc]].is_none() {
+            first[index[loc]] = Some(j);
+            found[index[loc]].fetch_min(chunk, Ordering::Relaxed);
+        }
+    }
+
+    true
+}
+
+impl<N: Nimber, B: BitSet> Game<N, B> {
+    /// Continue `prove` for the divisions of a heap of size m with the pairs of heaps (j, m - j)
+    /// of the store for j in `pairs`, split between `threads` threads.
+    ///
+    /// The pairs are checked in rounds of one chunk for each thread, and the result is the same
+    /// as if they were checked one by one: the values found are set in mex, and if all values
+    /// smaller than first_common were found, the pair which found the last of them is returned.
+    pub(super) fn prove_parallel(
+        &self,
+        g: &(impl NimberStore<N> + Sync),
+        m: usize,
+        pairs: Range<usize>,
+        first_common: usize,
+        mex: &mut B,
+        remaining_unset: &mut usize,
+    ) -> Option<usize> {
+        let missing: Vec<usize> = (0..first_common).filter(|&w| !mex.get(w)).collect();
+        let mut index = vec![usize::MAX; first_common];
+        for (k, &w) in missing.iter().enumerate() {
+            index[w] = k;
+        }
+        let found: Vec<AtomicUsize> = missing
+            .iter()
+            .map(|_| AtomicUsize::new(usize::MAX))
+            .collect();
+
+        let threads = self.threads;
+        let first_block = self.thresholds.first_block;
+        let (pairs, index, found) = (&pairs, &index, &found);
+        let firsts: Vec<Vec<Option<usize>>> = thread::scope(|s| {
+            let handles: Vec<_> = (0..threads)
+                .map(|t| {
+                    s.spawn(move || {
+                        // the chunk of the thread t in the round r is r * threads + t, and the
+                        // chunks of a thread only grow, so its first pairs are found first
+                        let mut first = vec![None; found.len()];
+                        let (mut start, mut block, mut chunk) = (pairs.start, first_block, t);
+                        while start < pairs.end {
+                            let a = std::cmp::min(pairs.end, start + t * block);
+                            let chunk_pairs = a..std::cmp::min(pairs.end, a + block);
+                            if !first_pairs(g, m, chunk_pairs, chunk, index, found, &mut first) {
+                                break;
+                            }
+                            start = std::cmp::min(pairs.end, start + threads * block);
+                            block *= 2;
+                            chunk += threads;
+                        }
+                        first
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        // the first pair with a value is the first one of all the threads
+        let mut last = 0;
+        for (k, &w) in missing.iter().enumerate() {
+            if let Some(j) = firsts.iter().filter_map(|first| first[k]).min() {
+                mex.set_bit(w);
+                *remaining_unset -= 1;
+                last = std::cmp::max(last, j);
+            }
+        }
+
+        if *remaining_unset == 0 {
+            Some(last)
+        } else {
+            None
+        }
+    }
+}
+
+/// `set_seen_bits_from_rare` with the heaps split between `threads` threads, each setting the
+/// values it finds in its own bitset.
+pub(super) fn set_seen_bits_from_rare_parallel<N: Nimber, B: BitSet>(
+    rules: &[Rule],
+    rare: &[(usize, N)],
+    g: &(impl NimberStore<N> + Sync),
+    n: usize,
+    threads: usize,
+    seen: &mut B,
+) {
+    let mut empty = seen.clone();
+    empty.zero_bits();
+
+    let step = rare.len().div_ceil(threads);
+    let found: Vec<B> = thread::scope(|s| {
+        let handles: Vec<_> = rare
+            .chunks(step)
+            .map(|heaps| {
+                let mut seen = empty.clone();
+                s.spawn(move || {
+                    for (i, rule) in rules.iter().enumerate() {
+                        if !rule.divide {
+                            continue;
+                        }
+                        // the heaps are sorted, so the rest are too large as well
+                        let fit = heaps.partition_point(|&(idx, _)| n > i + idx);
+                        for &(idx, x) in &heaps[..fit] {
+                            seen.set_bit((x ^ g.get(n - i - idx)).to_usize());
+                        }
+                    }
+                    seen
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    for other in &found {
+        seen.union_with(other);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parallel() {
+        for (rules_str, levels) in [
+            ("0.034", 1),
+            ("0.16", 1),
+            ("0.104", 2),
+            ("0.(14)", 1),
+            ("0.B4", 2),
+        ] {
+            let max = 400;
+            // the thresholds of the runs, and ones small enough for the parallel code to run on
+            // this short sequence
+            let small = Thresholds {
+                serial_pairs: 1 << 4,
+                first_block: 1 << 4,
+                parallel_heaps: 1 << 4,
+            };
+            let games: Vec<Game<u16>> = [
+                (1, Thresholds::default()),
+                (4, Thresholds::default()),
+                (4, small),
+                (3, small),
+            ]
+            .into_iter()
+            .map(|(threads, thresholds)| {
+                let mut g = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
+                g.levels = levels;
+                g.threads = threads;
+                g.thresholds = thresholds;
+                g.init();
+                for n in g.rules.len()..max {
+                    g.calc_rc(n).unwrap();
+                }
+                g
+            })
+            .collect();
+
+            // the pairs are checked in parallel, but the results do not depend on the threads
+            for g in &games[1..] {
+                assert_eq!(g.nimbers.g, games[0].nimbers.g, "for game {}", rules_str);
+                assert_eq!(g.stats, games[0].stats, "for game {}", rules_str);
+            }
+        }
+    }
+}
@@ -0,0 +1,670 @@
+use super::{max_heaps, periodicity_bound, rule_at, Nimber, NimberStore, Rule};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+
+/// Looks for a period of the nimbers while they are computed, and reports it as soon as the
+/// periodicity theorem confirms it (see `periodicity_bound`).
+///
+/// A period p with the start s is confirmed after the heap `periodicity_bound(s, p) - 1`, if the
+/// nimbers repeat with p from s on up to it.  Every period waits in a queue for that heap, and
+/// only then the repeats are checked backwards from the last heap: most periods break right
+/// there, the last heap which breaks them gives the next start, and the period waits again for
+/// a heap max_heaps times further.  So each period is checked a logarithmic number of times, and
+/// almost every check ends within a few heaps.
+///
+/// The periods longer than `max_period` are left to `Game::check_period`, which keeps the memory
+/// of the detector bounded and makes the heaps it reads in tail mode stay in the tail ring.
+pub struct PeriodDetector {
+    rules: Vec<Rule>,
+    max_period: usize,
+    /// The shortest period which was not queued yet.
+    next_period: usize,
+    /// The periods with the number of heaps which confirms them, the smallest first.
+    queue: BinaryHeap<Reverse<(usize, usize)>>,
+}
+
+impl PeriodDetector {
+    pub fn new(rules: &[Rule], max_period: usize) -> Self {
+        Self {
+            rules: rules.to_vec(),
+            max_period,
+            next_period: 1,
+            queue: BinaryHeap::new(),
+        }
+    }
+
+    /// Forget the periods longer than max_period.
+    pub fn truncate(&mut self, max_period: usize) {
+        self.max_period = std::cmp::min(self.max_period, max_period);
+        self.queue.retain(|Reverse((_, p))| *p <= max_period);
+        self.next_period = std::cmp::min(self.next_period, max_period + 1);
+    }
+
+    /// The start of the period p which is confirmed after `count` heaps.
+    fn start(&self, count: usize, p: usize) -> usize {
+        (count + 1 - self.rules.len()) / max_heaps(&self.rules) - p
+    }
+
+    /// Record that the heaps 0..count were computed, and return the start and the period of the
+    /// nimbers if they are confirmed by now.
+    pub fn update<N: Nimber>(
+        &mut self,
+        g: &impl NimberStore<N>,
+        count: usize,
+    ) -> Option<(usize, usize)> {
+        // a period is confirmed after periodicity_bound(0, p) heaps at the earliest
+        while self.next_period <= self.max_period
+            && periodicity_bound(&self.rules, 0, self.next_period) <= count
+        {
+            let p = self.next_period;
+            self.queue
+                .push(Reverse((periodicity_bound(&self.rules, 0, p), p)));
+            self.next_period += 1;
+        }
+
+        while let Some(&Reverse((confirmed, p))) = self.queue.peek() {
+            if confirmed > count {
+                break;
+            }
+            self.queue.pop();
+
+            let start = self.start(confirmed, p);
+            let mut k = count;
+            while k > start + p && g.get(k - 1) == g.get(k - 1 - p) {
+                k -= 1;
+            }
+            if k == start + p {
+                return Some((start, p));
+            }
+
+            // the heap k - 1 breaks the period, which may only start after it
+            let start = k - p;
+            let confirmed = periodicity_bound(&self.rules, start, p);
+            if confirmed <= count {
+                return Some((start, p));
+            }
+            self.queue.push(Reverse((confirmed, p)));
+        }
+
+        None
+    }
+}
+
+/// The lengths kept by `find_period`, u32 for the sequences shorter than 2^32 heaps, which
+/// halves its memory.
+trait Length: Copy {
+    fn from_usize(x: usize) -> Self;
+    fn to_usize(self) -> usize;
+}
+
+impl Length for u32 {
+    fn from_usize(x: usize) -> Self {
+        x as u32
+    }
+
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
+
+impl Length for usize {
+    fn from_usize(x: usize) -> Self {
+        x
+    }
+
+    fn to_usize(self) -> usize {
+        self
+    }
+}
+
+/// What `find_period` and `find_period_in_batches` found in the first `count` nimbers.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Search {
+    /// The shortest period confirmed by the periodicity theorem, with its start.
+    Period(usize, usize),
+    /// No period is confirmed, the start and the period which repeats for the most heaps back
+    /// from the last one (count - start - period, none if even the last heap breaks it), the
+    /// shortest one if there are more, if any.  The theorem needs at least two periods of
+    /// nimbers, so the periods up to count / 2 are the candidates, and the candidate is
+    /// confirmed if the nimbers go on repeating up to `periodicity_bound(start, period)`.
+    Candidate(Option<(usize, usize)>),
+}
+
+/// The shortest period of the first `count` nimbers confirmed by the periodicity theorem, with
+/// its start, or the best candidate if there is none, in linear time.
+///
+/// Read backwards from the last heap, the nimbers repeat with the period p for as many heaps as
+/// the sequence read backwards has in common with itself shifted by p, which is the Z-function
+/// of the reversed sequence at p.  So the start of every period is known after a single pass
+/// over the periods, in order, and the pass stops at the first period the theorem confirms, or
+/// once no longer period can be confirmed or repeat for more heaps than the best candidate.
+/// The result is the same as trying every period and walking back to its start.
+pub fn find_period<N: Nimber>(g: &impl NimberStore<N>, rules: &[Rule], count: usize) -> Search {
+    // the periods which may be confirmed, periodicity_bound(0, p) <= count
+    let heaps = max_heaps(rules);
+    let longest = (count + 1).saturating_sub(rules.len()) / heaps;
+
+    let mut found = None;
+    let mut best: Option<(usize, usize)> = None;
+    // the candidates include all the periods which may be confirmed, since longest <= count / 2
+    repeats(g, count, count / 2, |p, len| {
+        let start = count - p - len;
+        if p <= longest && count >= periodicity_bound(rules, start, p) {
+            found = Some((start, p));
+            return true;
+        }
+        if best.is_none_or(|(start, period)| len > count - start - period) {
+            best = Some((start, p));
+        }
+        // the longer periods repeat for at most count - p - 1 heaps
+        let best_len = best.map_or(0, |(start, period)| count - start - period);
+        p >= longest && count - p - 1 <= best_len
+    });
+
+    match found {
+        Some((start, period)) => Search::Period(start, period),
+        None => Search::Candidate(best),
+    }
+}
+
+/// Call `f` with every period 1..=longest in order, and the number of heaps the first `count`
+/// nimbers repeat with it backwards from the last one, until `f` returns true.
+fn repeats<N: Nimber>(
+    g: &impl NimberStore<N>,
+    count: usize,
+    longest: usize,
+    f: impl FnMut(usize, usize) -> bool,
+) {
+    if count <= u32::MAX as usize {
+        z_function::<N, u32>(g, count, longest, f)
+    } else {
+        z_function::<N, usize>(g, count, longest, f)
+    }
+}
+
+fn z_function<N: Nimber, L: Length>(
+    g: &impl NimberStore<N>,
+    count: usize,
+    longest: usize,
+    mut f: impl FnMut(usize, usize) -> bool,
+) {
+    let reversed = |i: usize| g.get(count - 1 - i);
+    // z[p] is the Z-function of the reversed sequence at p, and [left, right) is the rightmost
+    // range found equal to its prefix; z grows with the pass, which usually stops early
+    let mut z: Vec<L> = vec![L::from_usize(count)];
+    let (mut left, mut right) = (0, 0);
+    for p in 1..=longest {
+        let mut len = if p < right {
+            std::cmp::min(right - p, z[p - left].to_usize())
+        } else {
+            0
+        };
+        while p + len < count && reversed(len) == reversed(p + len) {
+            len += 1;
+        }
+        if p + len > right {
+            (left, right) = (p, p + len);
+        }
+        z.push(L::from_usize(len));
+
+        if f(p, len) {
+            return;
+        }
+    }
+}
+
+/// `find_period` for sequences which do not fit in memory, like the rings saved on disk, which
+/// reads them in order instead of keeping a length for every period.
+///
+/// The periods are checked in batches of `batch`, each in a single pass backwards from the last
+/// heap, comparing every heap k with the heaps k - p of the periods p of the batch which still
+/// repeat.  Almost all of them break within a few heaps, so a pass reads the heaps at the end and
+/// a window of `batch` heaps moving back with it, which is read from consecutive rings.  The
+/// theorem confirms the period p when it repeats for all the heaps from
+/// (count + 1 - rules.len()) / max_heaps on, the same for every p, so the periods still
+/// repeating there are all confirmed, and the shortest is the result.  Where a period breaks
+/// gives its start, so the best candidate is known from the same passes, which go on up to the
+/// periods of count / 2 unless no longer period can repeat for more heaps.
+pub fn find_period_in_batches<N: Nimber>(
+    g: &impl NimberStore<N>,
+    rules: &[Rule],
+    count: usize,
+    batch: usize,
+) -> Search {
+    let heaps = max_heaps(rules);
+    // 0 if no period can be confirmed yet
+    let confirmed_from = (count + 1).saturating_sub(rules.len()) / heaps;
+    let longest = count / 2;
+
+    let mut best: Option<(usize, usize)> = None;
+    for first in (1..=longest).step_by(batch) {
+        // a period p repeats for at most count - p heaps
+        let best_len = best.map_or(0, |(start, period)| count - start - period);
+        if first > confirmed_from && count - first <= best_len {
+            break;
+        }
+
+        let mut periods: Vec<usize> = (first..std::cmp::min(first + batch, longest + 1)).collect();
+        let mut k = count;
+        while !periods.is_empty() {
+            if k == confirmed_from && periods[0] <= confirmed_from {
+                // the start is after the last heap which breaks the period
+                let period = periods[0];
+                while k > period && g.get(k - 1) == g.get(k - 1 - period) {
+                    k -= 1;
+                }
+                return Search::Period(k - period, period);
+            }
+
+            k -= 1;
+            let x = g.get(k);
+            periods.retain(|&p| {
+                if k >= p && g.get(k - p) == x {
+                    return true;
+                }
+                // the period repeats from the heap k + 1 - p on, the shortest of the longest
+                // repeats is the candidate
+                let start = k + 1 - p;
+                if best.is_none_or(|(s, period)| (start + p, p) < (s + period, period)) {
+                    best = Some((start, p));
+                }
+                false
+            });
+        }
+    }
+
+    Search::Candidate(best)
+}
+
+/// The shortest arithmetic period of the first `count` nimbers, with its start and saltus, that
+/// is g(n + period) == g(n) + saltus for all n >= start, if the sufficient condition below
+/// confirms it.  Reads the nimbers in the order of `find_period_in_batches`, checking the periods
+/// in batches of `batch`, so it works on the rings saved on disk as well as on the front.
+///
+/// Let P = rules.len(), q = repeating.len() (1 for finite codes), k be the largest number of
+/// heaps left by a move (1 if the game never divides heaps) and let period be a multiple of q.
+/// Split the successors of a heap N into the "high" ones, which leave at least one heap of size at
+/// least start, and the "low" ones, which leave only heaps smaller than start (L(N)).  Similarly,
+/// split the successors of N + period into those leaving a heap of size at least start + period,
+/// and the low ones, leaving only heaps smaller than start + period (L'(N + period)).  Removing
+/// the same i tokens and moving period tokens between the large heap and the rest maps the high
+/// successors of N onto the high successors of N + period.  If k == 1 these differ by saltus,
+/// and if k > 1 only saltus == 0 keeps that true, since the values are xor-ed.
+///
+/// The low successors only exist for i > N - k * (start + period), so for
+/// N >= P + k * start + (k - 1) * period they only depend on N mod q.  If for each residue class
+/// * L'(N + period) contains all of 0..saltus,
+/// * the values of L'(N + period) not smaller than saltus are exactly L(N) + saltus,
+///
+/// then mex of the successors of N + period is the mex of the successors of N plus saltus.
+/// So if additionally g(n + period) == g(n) + saltus was observed for all
+/// start <= n < P + k * start + (k - 1) * period, then by induction it holds for all n >= start.
+pub fn find_arithmetic_period<N: Nimber>(
+    g: &impl NimberStore<N>,
+    rules: &[Rule],
+    repeating: &[Rule],
+    count: usize,
+    batch: usize,
+) -> Option<(usize, usize, usize)> {
+    let q = std::cmp::max(repeating.len(), 1);
+    let heaps = heaps_left(rules, repeating);
+    // the nimbers have to repeat from the heap confirmed_from on, for the start of the period p
+    // to be at most confirmed_from - p, which the condition needs, and the start is at least 1
+    let confirmed_from = count.checked_sub(rules.len())? / heaps;
+    let longest = std::cmp::min(confirmed_from.checked_sub(1)?, count / 2);
+
+    let value = |m: usize| g.get(m).to_usize();
+    let last = value(count - 1);
+
+    for first in (q..=longest).step_by(q * batch) {
+        // the saltus of each period is known from the last heap
+        let mut periods: Vec<(usize, usize)> = (first
+            ..=std::cmp::min(first + q * (batch - 1), longest))
+            .step_by(q)
+            .filter_map(|p| {
+                let saltus = last.checked_sub(value(count - 1 - p))?;
+                Some((p, saltus)).filter(|_| heaps == 1 || saltus == 0)
+            })
+            .collect();
+        for k in (confirmed_from..count - 1).rev() {
+            if periods.is_empty() {
+                break;
+            }
+            let x = value(k);
+            periods.retain(|&(p, saltus)| value(k - p) + saltus == x);
+        }
+
+        for (period, saltus) in periods {
+            // the start is after the last heap which breaks the period
+            let mut start = confirmed_from - period;
+            while start > 1 && value(start - 1 + period) == value(start - 1) + saltus {
+                start -= 1;
+            }
+
+            let induction_start = rules.len() + heaps * start + (heaps - 1) * period;
+            let confirmed = (induction_start..induction_start + q).all(|m| {
+                let low = low_successors(g, rules, repeating, m, start);
+                let low_shifted = low_successors(g, rules, repeating, m + period, start + period);
+
+                (0..saltus).all(|x| low_shifted.contains(&x))
+                    && low_shifted.iter().filter(|&&x| x >= saltus).count() == low.len()
+                    && low.iter().all(|x| low_shifted.contains(&(x + saltus)))
+            });
+            if confirmed {
+                return Some((start, period, saltus));
+            }
+        }
+    }
+
+    None
+}
+
+/// The largest number of heaps a move may leave, 1 if the game never divides heaps.
+fn heaps_left(rules: &[Rule], repeating: &[Rule]) -> usize {
+    let rules = || rules.iter().chain(repeating.iter());
+    if rules().any(|r| r.divide3) {
+        3
+    } else if rules().any(|r| r.divide) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Values of the successors of a heap of size m which leave only heaps smaller than bound.
+fn low_successors<N: Nimber>(
+    g: &impl NimberStore<N>,
+    rules: &[Rule],
+    repeating: &[Rule],
+    m: usize,
+    bound: usize,
+) -> HashSet<usize> {
+    let mut low = HashSet::new();
+
+    for left in 0..=std::cmp::min(m - 1, heaps_left(rules, repeating) * (bound - 1)) {
+        let rule = rule_at(rules, repeating, m - left);
+
+        if left == 0 && rule.all {
+            low.insert(0);
+        }
+        if rule.some && 0 < left && left < bound {
+            low.insert(g.get(left).to_usize());
+        }
+        if rule.divide {
+            for a in left.saturating_sub(bound - 1).max(1)..=left / 2 {
+                low.insert((g.get(a) ^ g.get(left - a)).to_usize());
+            }
+        }
+        if rule.divide3 {
+            for a in 1..=left / 3 {
+                for b in a..=(left - a) / 2 {
+                    if left - a - b < bound {
+                        low.insert((g.get(a) ^ g.get(b) ^ g.get(left - a - b)).to_usize());
+                    }
+                }
+            }
+        }
+    }
+
+    low
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::testing::{front, TempDir};
+    use super::super::{checkpoint, chunk, rules_from_str, Game, History, Storage};
+    use super::*;
+
+    /// The shortest period confirmed after count heaps, trying every period.
+    fn naive_period(g: &[u16], rules: &[Rule], count: usize) -> Option<(usize, usize)> {
+        (1..count).find_map(|period| {
+            let mut start = count - period;
+            while start > 0 && g[start - 1] == g[start - 1 + period] {
+                start -= 1;
+            }
+            Some((start, period)).filter(|_| count >= periodicity_bound(rules, start, period))
+        })
+    }
+
+    /// The longest repeat, trying every period.
+    fn naive_candidate(g: &[u16], count: usize) -> Option<(usize, usize)> {
+        (1..=count / 2)
+            .map(|period| {
+                let mut start = count - period;
+                while start > 0 && g[start - 1] == g[start - 1 + period] {
+                    start -= 1;
+                }
+                (start, period)
+            })
+            .min_by_key(|&(start, period)| (start + period, period))
+    }
+
+    #[test]
+    fn test_period_detector() {
+        for (rules_str, periodic) in [
+            ("0.07", true),
+            ("0.137", true),
+            ("0.77", true),
+            ("4.7", true),
+            ("0.16", false),
+            ("0.104", false),
+        ] {
+            let max = 1000;
+            let g: Game<u16> = front(rules_str, max, |_| {});
+            let values = g.nimbers.g.to_vec();
+
+            let mut detector = PeriodDetector::new(&g.rules, max);
+            let found = (1..=max).find_map(|count| {
+                detector
+                    .update(&g.nimbers.front(), count)
+                    .map(|p| (count, p))
+            });
+            assert_eq!(found.is_some(), periodic, "for game {}", rules_str);
+
+            // reported as soon as the whole search finds a period, with a period it accepts
+            if let Some((count, (start, period))) = found {
+                assert_eq!(
+                    naive_period(&values, &g.rules, count - 1),
+                    None,
+                    "for game {}",
+                    rules_str
+                );
+                assert!(
+                    naive_period(&values, &g.rules, count).is_some(),
+                    "for game {}",
+                    rules_str
+                );
+                assert!(
+                    count >= periodicity_bound(&g.rules, start, period),
+                    "for game {}",
+                    rules_str
+                );
+                assert!((start..count - period).all(|k| values[k] == values[k + period]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_period() {
+        // sequences of the games, and sequences of a few values with periods after some noise
+        let mut sequences: Vec<(Vec<Rule>, Vec<u16>)> = vec![];
+        for rules_str in ["0.07", "0.137", "0.77", "4.7", "0.16", "0.104", "0.B4"] {
+            let max = 600;
+            let g: Game<u16> = front(rules_str, max, |_| {});
+            sequences.push((g.rules.clone(), g.nimbers.g.to_vec()));
+        }
+        let mut x: u64 = 12345;
+        for (values, period, noise) in
+            [(2, 1, 10), (3, 7, 40), (2, 30, 5), (4, 13, 100), (2, 64, 0)]
+        {
+            let pattern: Vec<u16> = (0..period)
+                .map(|_| {
+                    x = x
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    ((x >> 33) % values) as u16
+                })
+                .collect();
+            let mut g: Vec<u16> = (0..noise)
+                .map(|_| {
+                    x = x
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    ((x >> 33) % values) as u16
+                })
+                .collect();
+            g.extend((0..400).map(|k| pattern[k % period]));
+            sequences.push((rules_from_str("0.07"), g.clone()));
+            sequences.push((rules_from_str("0.B4"), g));
+        }
+
+        for (rules, g) in &sequences {
+            let store = Storage::Memory(g.clone());
+            for count in 0..=g.len() {
+                let expected = naive_period(g, rules, count);
+                assert_eq!(
+                    find_period(&store, rules, count),
+                    match expected {
+                        Some((start, period)) => Search::Period(start, period),
+                        None => Search::Candidate(naive_candidate(g, count)),
+                    },
+                    "for {:?} at {}",
+                    g,
+                    count
+                );
+                for batch in [1, 5, 64] {
+                    assert_eq!(
+                        find_period_in_batches(&store, rules, count, batch),
+                        find_period(&store, rules, count),
+                        "for {:?} at {} in batches of {}",
+                        g,
+                        count,
+                        batch
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_candidate_at_end() {
+        // no heap has the value of the one before it, so the best candidate is the period 1
+        // from the last heap on, which repeats for no heaps
+        let rules = rules_from_str("0.07");
+        let store = Storage::Memory((0..20).collect::<Vec<u16>>());
+        for count in 0..=20_usize {
+            // the candidates are the periods up to count / 2
+            let expected = Search::Candidate(if count >= 2 {
+                Some((count - 1, 1))
+            } else {
+                None
+            });
+            assert_eq!(find_period(&store, &rules, count), expected, "at {}", count);
+            for batch in [1, 5] {
+                assert_eq!(
+                    find_period_in_batches(&store, &rules, count, batch),
+                    expected,
+                    "at {} in batches of {}",
+                    count,
+                    batch
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_period_detector_tail() {
+        let dir = TempDir::new("period");
+
+        // confirmed after 176 and 168 heaps, found in the tail ring or reading the history
+        for (rules_str, expected, ring) in [
+            ("0.07", (53, 34), 64),
+            ("0.77", (71, 12), 32),
+            ("0.77", (71, 12), 16),
+        ] {
+            let max_full_memory = 64;
+            let mut g = Game::<u16>::new(rules_str.parse().unwrap(), max_full_memory, ring);
+            g.init();
+            let mut detector = PeriodDetector::new(&g.rules, max_full_memory);
+            for n in g.rules.len()..max_full_memory {
+                g.calc_rc(n).unwrap();
+                assert_eq!(
+                    detector.update(&g.nimbers.front(), n + 1),
+                    None,
+                    "for game {}",
+                    rules_str
+                );
+            }
+            g.nimbers.copy_to_g_back();
+            g.use_history(&dir);
+            detector.truncate(ring);
+
+            let mut found = None;
+            for n in max_full_memory..1000 {
+                if n % ring == 0 {
+                    g.save_checkpoint(&dir, n).unwrap();
+                }
+                g.calc_rc_back(n).unwrap();
+                found = detector.update(&g.sequence(n + 1), n + 1);
+                if found.is_some() {
+                    break;
+                }
+            }
+            assert_eq!(
+                found,
+                Some(expected),
+                "for game {} with ring {}",
+                rules_str,
+                ring
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_arithmetic_period() {
+        let dir = TempDir::new("arithmetic");
+
+        for (rules_str, expected) in [
+            ("0.(3)", Some((1, 1, 1))),
+            ("0.(12)", Some((1, 2, 1))),
+            ("0.0(3)", Some((1, 2, 1))),
+            ("0.1(23)", Some((3, 2, 1))),
+            ("0.3(1)", Some((1, 2, 0))),
+            ("0.(4)", None),
+            ("0.07", Some((53, 34, 0))),
+            ("0.77", Some((71, 12, 0))),
+        ] {
+            let (count, max, ring) = (200, 400, 8);
+            let g: Game<u16> = front(rules_str, max, |_| {});
+            let values = g.nimbers.g.to_vec();
+
+            // the same nimbers saved in rings, as the tail mode saves them
+            let code = g.code.to_string();
+            for end in (ring..=count).step_by(ring) {
+                let path = checkpoint::nimbers_path(&dir, &code, end);
+                let ring = Storage::Memory(values[end - ring..end].to_vec());
+                chunk::write_storage(&path, &code, end - ring.len(), &ring).unwrap();
+            }
+            let history = History::<u16>::new(dir.to_path_buf(), code, ring).with_capacity(2);
+
+            let found =
+                find_arithmetic_period(&g.nimbers.front(), &g.rules, &g.repeating, count, 1024);
+            assert_eq!(found, expected, "for game {}", rules_str);
+            for batch in [1, 3] {
+                assert_eq!(
+                    find_arithmetic_period(&history, &g.rules, &g.repeating, count, batch),
+                    expected,
+                    "for game {} in batches of {}",
+                    rules_str,
+                    batch
+                );
+            }
+
+            // the period holds past the heaps it was confirmed with
+            if let Some((start, period, saltus)) = found {
+                assert!((start..max - period)
+                    .all(|k| values[k + period] as usize == values[k] as usize + saltus));
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use super::period::find_arithmetic_period;
+use super::{for_each_triple, BitSet, Game, Nimber};
+
+/// Periods `check_arithmetic_period` checks in each pass over the front.  The front is in
+/// memory, so the passes are cheap, and a batch only bounds the memory of the candidates.
+const ARITHMETIC_BATCH: usize = 1 << 10;
+
+/// Indices of first occurrences of nimbers among heap sizes from the same residue class.
+///
+/// Games with a repeating code allow removing i tokens for infinitely many i from each class
+/// modulo the length of the repeating part, so the successors of n reach all heaps m <= n - i from
+/// a single residue class.  Instead of iterating over all of them, it is enough to know which
+/// nimbers first occur in the class before the bound.
+pub struct FirstOccurrences {
+    first: Vec<Vec<usize>>,
+}
+
+impl FirstOccurrences {
+    pub fn new(classes: usize) -> Self {
+        Self {
+            first: vec![vec![]; classes],
+        }
+    }
+
+    pub fn record(&mut self, m: usize, nim: usize) {
+        if self.first.is_empty() {
+            return;
+        }
+
+        let len = self.first.len();
+        let first = &mut self.first[m % len];
+        if first.len() <= nim {
+            first.resize(nim + 1, usize::MAX);
+        }
+        first[nim] = first[nim].min(m);
+    }
+
+    /// Is there a heap 1 <= m <= bound, m == bound (mod classes) such that g[m] == nim?
+    pub fn occurs_up_to(&self, bound: usize, nim: usize) -> bool {
+        let first = &self.first[bound % self.first.len()];
+        nim < first.len() && first[nim] <= bound
+    }
+
+    /// Call `f` with every nimber for which `occurs_up_to(bound, nimber)` is true.
+    pub fn for_each_up_to(&self, bound: usize, mut f: impl FnMut(usize)) {
+        for (nim, &m) in self.first[bound % self.first.len()].iter().enumerate() {
+            if m <= bound {
+                f(nim);
+            }
+        }
+    }
+}
+
+impl<N: Nimber, B: BitSet> Game<N, B> {
+    /// Set the values of all successors of n reachable by removing i >= rules.len() tokens, except
+    /// for divisions into two heaps, which are handled by the sparse space routines.
+    pub(super) fn set_seen_bits_from_repeating_moves(&mut self, n: usize) {
+        let first_repeating = self.rules.len();
+
+        if self.rule(n).all {
+            self.bits.seen.set_bit(0);
+        }
+
+        for (r, rule) in self.repeating.iter().enumerate() {
+            if rule.some && n > first_repeating + r {
+                let bound = n - first_repeating - r;
+                self.nimbers
+                    .first
+                    .for_each_up_to(bound, |nim| self.bits.seen.set_bit(nim));
+            }
+        }
+
+        for i in first_repeating..n {
+            if self.rule(i).divide3 {
+                for_each_triple(&self.nimbers.g, n - i, |x| self.bits.seen.set_bit(x));
+            }
+        }
+    }
+
+    /// Iterate over x ^ y such that x stops being common at the level, for divisions after
+    /// removing i >= rules.len() tokens.
+    pub(super) fn iterate_over_r_xor_c_repeating(&mut self, n: usize, level: usize) {
+        let first_repeating = self.rules.len();
+
+        for (r, rule) in self.repeating.iter().enumerate() {
+            if rule.divide {
+                for &(idx, x) in self.nimbers.rare_at(level) {
+                    if n > first_repeating + r + idx {
+                        let bound = n - first_repeating - r - idx;
+                        self.nimbers.first.for_each_up_to(bound, |y| {
+                            self.bits.seen.set_bit(x.to_usize() ^ y);
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Continue `prove` with divisions after removing i >= rules.len() tokens.
+    ///
+    /// For each heap a, the other heap b (a + b == n - i) may be any heap from a single residue
+    /// class up to some bound, so instead of iterating over b, it is enough to check if any of the
+    /// missing values w has its g[a] ^ w in that class.
+    ///
+    /// Returns the heap a for which all values smaller than first_common were found, if they were.
+    pub(super) fn prove_repeating(
+        &self,
+        n: usize,
+        first_common: usize,
+        mex: &mut B,
+        remaining_unset: &mut usize,
+    ) -> Option<usize> {
+        let first_repeating = self.rules.len();
+
+        for a in 1..n.saturating_sub(first_repeating) {
+            let x = self.nimbers.g.get(a).to_usize();
+            for (r, rule) in self.repeating.iter().enumerate() {
+                if !rule.divide || n <= first_repeating + r + a {
+                    continue;
+                }
+
+                let bound = n - first_repeating - r - a;
+                for w in 0..first_common {
+                    if !mex.get(w) && self.nimbers.first.occurs_up_to(bound, x ^ w) {
+                        mex.set_bit(w);
+                        *remaining_unset -= 1;
+                        if *remaining_unset == 0 {
+                            return Some(a);
+                        }
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// The start, the period and the saltus of the game with a repeating code, if
+    /// g(n + period) == g(n) + saltus for all n >= start is confirmed using only g[0..n] (see
+    /// `period::find_arithmetic_period`).
+    ///
+    /// The condition only confirms a saltus of 0 for games dividing heaps, whose values are
+    /// xor-ed, so their arithmetic periods with a positive saltus are not found.
+    pub fn check_arithmetic_period(&self, n: usize) -> Option<(usize, usize, usize)> {
+        find_arithmetic_period(
+            &self.nimbers.front(),
+            &self.rules,
+            &self.repeating,
+            n,
+            ARITHMETIC_BATCH,
+        )
+    }
+}
@@ -0,0 +1,131 @@
+/// Scanning pairs of heaps for the values still missing among the successors of a heap, a block
+/// of pairs at a time.
+///
+/// The pairs (j, m - j) are given as two slices read in the same direction, the second one taken
+/// from the reversed sequence, so both can be loaded into vector registers.
+pub trait Scan: Copy + PartialEq + std::ops::BitXor<Output = Self> {
+    /// Whether `first_xor_in` uses vector instructions.  Otherwise it is no faster than the loop
+    /// over the pairs, so the reversed copies it needs are not kept.
+    const VECTOR: bool;
+
+    /// The first k such that a[k] ^ b[k] is one of wanted, checking min(a.len(), b.len()) pairs.
+    fn first_xor_in(a: &[Self], b: &[Self], wanted: &[Self]) -> Option<usize>;
+}
+
+/// The scalar version of `Scan::first_xor_in`, also used for the pairs after the last block.
+pub fn first_xor_in_scalar<T: Scan>(a: &[T], b: &[T], wanted: &[T]) -> Option<usize> {
+    a.iter()
+        .zip(b)
+        .position(|(&x, &y)| wanted.contains(&(x ^ y)))
+}
+
+/// SSE2 is a part of x86_64, so it needs no runtime detection.
+#[cfg(target_arch = "x86_64")]
+macro_rules! impl_scan_sse2 {
+    ($($t:ty => $signed:ty, $set1:ident, $cmpeq:ident);*) => {
+        $(
+            impl Scan for $t {
+                const VECTOR: bool = true;
+
+                fn first_xor_in(a: &[Self], b: &[Self], wanted: &[Self]) -> Option<usize> {
+                    use std::arch::x86_64::*;
+
+                    const LANES: usize = 16 / std::mem::size_of::<$t>();
+                    let len = std::cmp::min(a.len(), b.len());
+                    let blocks = len / LANES;
+
+                    for block in 0..blocks {
+                        let k = block * LANES;
+                        // SAFETY: SSE2 is always available on x86_64, and k + LANES <= len, so
+                        // the unaligned loads stay within both slices
+                        let hits = unsafe {
+                            let x = _mm_loadu_si128(a.as_ptr().add(k) as *const __m128i);
+                            let y = _mm_loadu_si128(b.as_ptr().add(k) as *const __m128i);
+                            let xor = _mm_xor_si128(x, y);
+                            let mut hit = _mm_setzero_si128();
+                            for &w in wanted {
+                                hit = _mm_or_si128(hit, $cmpeq(xor, $set1(w as $signed)));
+                            }
+                            _mm_movemask_epi8(hit)
+                        };
+
+                        if hits != 0 {
+                            // movemask has a bit for each byte of the lanes
+                            return Some(k + hits.trailing_zeros() as usize / std::mem::size_of::<$t>());
+                        }
+                    }
+
+                    let k = blocks * LANES;
+                    first_xor_in_scalar(&a[k..len], &b[k..len], wanted).map(|i| k + i)
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(target_arch = "x86_64")]
+impl_scan_sse2!(
+    u8 => i8, _mm_set1_epi8, _mm_cmpeq_epi8;
+    u16 => i16, _mm_set1_epi16, _mm_cmpeq_epi16;
+    u32 => i32, _mm_set1_epi32, _mm_cmpeq_epi32
+);
+
+macro_rules! impl_scan_scalar {
+    ($($t:ty),*) => {
+        $(
+            impl Scan for $t {
+                const VECTOR: bool = false;
+
+                fn first_xor_in(a: &[Self], b: &[Self], wanted: &[Self]) -> Option<usize> {
+                    first_xor_in_scalar(a, b, wanted)
+                }
+            }
+        )*
+    };
+}
+
+// SSE2 has no 64 bit comparisons
+#[cfg(target_arch = "x86_64")]
+impl_scan_scalar!(u64, u128);
+#[cfg(not(target_arch = "x86_64"))]
+impl_scan_scalar!(u8, u16, u32, u64, u128);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check<T: Scan + TryFrom<usize>>()
+    where
+        <T as TryFrom<usize>>::Error: std::fmt::Debug,
+    {
+        let t = |x: usize| T::try_from(x).unwrap();
+        // lengths around the block sizes, with the hit in every position
+        for len in [0, 1, 7, 8, 15, 16, 17, 33, 100] {
+            let a: Vec<T> = (0..len).map(|k| t(k % 5)).collect();
+            let b: Vec<T> = (0..len).map(|k| t(k % 5 + 8)).collect();
+            assert_eq!(T::first_xor_in(&a, &b, &[t(1), t(2)]), None);
+            assert_eq!(T::first_xor_in(&a, &b, &[]), None);
+
+            for hit in 0..len {
+                let mut b = b.clone();
+                b[hit] = a[hit] ^ t(3);
+                if hit + 1 < len {
+                    b[hit + 1] = a[hit + 1] ^ t(3);
+                }
+                assert_eq!(T::first_xor_in(&a, &b, &[t(1), t(3)]), Some(hit));
+                assert_eq!(first_xor_in_scalar(&a, &b, &[t(1), t(3)]), Some(hit));
+                // only the pairs of the shorter slice are checked
+                assert_eq!(T::first_xor_in(&a[..hit], &b, &[t(3)]), None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_first_xor_in() {
+        check::<u8>();
+        check::<u16>();
+        check::<u32>();
+        check::<u64>();
+        check::<u128>();
+    }
+}
@@ -0,0 +1,152 @@
+use super::{BitSet, Game, Nimber, Overflow, Proof};
+use std::thread;
+
+impl<N: Nimber, B: BitSet> Game<N, B> {
+    /// Compute the values of up to `speculation` heaps from n on (but none from end on), and
+    /// return the number of heaps computed.
+    ///
+    /// The candidates of the heaps are found one after another, each assuming that the heaps
+    /// before it have their candidates as values, which is what `prove` confirms for all but the
+    /// rare values.  Then the proofs are split between `threads` threads, and the values are
+    /// stored in order up to the first one which is not the candidate: the heaps after it were
+    /// computed from a wrong value, so they are left for the next call.
+    ///
+    /// The window of heaps also ends at a heap which resizes R, so that all the heaps in it use
+    /// the same sparse space.  Games with a repeating code are computed one heap at a time.
+    pub fn calc_rc_speculative(&mut self, n: usize, end: usize) -> Result<usize, Overflow> {
+        if self.speculation <= 1 || !self.repeating.is_empty() {
+            self.calc_rc(n)?;
+            return Ok(1);
+        }
+
+        let levels = self.bits.chain.len();
+        let lens: Vec<usize> = (0..levels).map(|l| self.nimbers.rare_at(l).len()).collect();
+        let eliminated = self.stats.eliminated.clone();
+
+        // the values each heap found without prove, and how many of them each level eliminated
+        let mut window = vec![];
+        for m in n..std::cmp::min(end, n + self.speculation) {
+            let before = self.stats.eliminated.clone();
+            self.set_seen_bits(m);
+            let candidate = self
+                .bits
+                .seen
+                .find_first_unset_also_unset_in(&self.bits.rare);
+            let counts: Vec<usize> = self
+                .stats
+                .eliminated
+                .iter()
+                .zip(&before)
+                .map(|(after, before)| after - before)
+                .collect();
+            window.push((self.bits.seen.clone(), counts));
+
+            if candidate > self.stats.largest_nimber.to_usize() || m.is_power_of_two() {
+                break;
+            }
+            let predicted = N::from_usize(candidate);
+            self.nimbers.set(m, predicted);
+            self.push_rare(m, predicted);
+        }
+
+        // the predicted heaps are added again as their values are stored
+        for (level, len) in lens.into_iter().enumerate() {
+            self.nimbers.rare_at_mut(level).truncate(len);
+        }
+        self.stats.eliminated = eliminated;
+
+        let game = &*self;
+        let prove = move |first: usize, heaps: &[(B, Vec<usize>)]| -> Vec<Proof> {
+            heaps
+                .iter()
+                .enumerate()
+                .map(|(d, (seen, _))| game.prove_seen(first + d, seen))
+                .collect()
+        };
+        let proofs: Vec<Proof> = if self.threads == 1 {
+            prove(n, &window)
+        } else {
+            let step = window.len().div_ceil(self.threads);
+            thread::scope(|s| {
+                let handles: Vec<_> = window
+                    .chunks(step)
+                    .enumerate()
+                    .map(|(c, heaps)| s.spawn(move || prove(n + c * step, heaps)))
+                    .collect();
+                handles
+                    .into_iter()
+                    .flat_map(|h| h.join().unwrap())
+                    .collect()
+            })
+        };
+
+        for (d, (proof, (_, counts))) in proofs.into_iter().zip(&window).enumerate() {
+            for (total, count) in self.stats.eliminated.iter_mut().zip(counts) {
+                *total += count;
+            }
+            let nim = self.record_proof(n + d, proof);
+            self.set_next_g_n(n + d, nim)?;
+
+            if let Proof::Rare(_) = proof {
+                return Ok(d + 1);
+            }
+        }
+
+        Ok(window.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::parallel::Thresholds;
+    use super::*;
+
+    #[test]
+    fn test_speculation() {
+        for (rules_str, levels, max) in [
+            ("0.034", 1, 1000),
+            ("0.16", 1, 1000),
+            ("0.104", 2, 1000),
+            ("0.376", 3, 1000),
+            ("0.B4", 1, 200),
+        ] {
+            let games: Vec<Game<u16>> = [(1, 1), (4, 1), (16, 3)]
+                .into_iter()
+                .map(|(speculation, threads)| {
+                    let mut g = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
+                    g.levels = levels;
+                    g.speculation = speculation;
+                    g.threads = threads;
+                    // small enough for the parallel code to run on this short sequence
+                    g.thresholds = Thresholds {
+                        serial_pairs: 1 << 4,
+                        first_block: 1 << 4,
+                        parallel_heaps: 1 << 4,
+                    };
+                    g.init();
+                    let mut n = g.rules.len();
+                    while n < max {
+                        n += g.calc_rc_speculative(n, max).unwrap();
+                    }
+                    g
+                })
+                .collect();
+
+            // the same values and statistics as when computing the heaps one by one
+            for g in &games[1..] {
+                assert_eq!(g.nimbers.g, games[0].nimbers.g, "for game {}", rules_str);
+                assert_eq!(
+                    g.nimbers.rare, games[0].nimbers.rare,
+                    "for game {}",
+                    rules_str
+                );
+                assert_eq!(
+                    g.nimbers.levels, games[0].nimbers.levels,
+                    "for game {}",
+                    rules_str
+                );
+                assert_eq!(g.stats, games[0].stats, "for game {}", rules_str);
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use super::mapped::MappedFront;
+use super::packed::Packed;
+use super::Nimber;
+use std::fmt;
+use std::io;
+
+/// Where the nimbers of the front (g) or the tail ring (g_back) are kept: in memory, in a file
+/// mapped into memory (only the front), or bit-packed in memory.
+///
+/// The algorithms read and write the nimbers with `get` and `set`, so they do not depend on the
+/// storage, and those which scan long runs of the nimbers use `as_slice` when it is available.
+pub enum Storage<N: Nimber> {
+    Memory(Vec<N>),
+    Mapped(MappedFront<N>),
+    Packed(Packed<N>),
+}
+
+impl<N: Nimber> Storage<N> {
+    pub fn len(&self) -> usize {
+        match self {
+            Storage::Memory(g) => g.len(),
+            Storage::Mapped(g) => g.len(),
+            Storage::Packed(g) => g.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline(always)]
+    pub fn get(&self, m: usize) -> N {
+        match self {
+            Storage::Memory(g) => g[m],
+            Storage::Mapped(g) => g[m],
+            Storage::Packed(g) => g.get(m),
+        }
+    }
+
+    #[inline]
+    pub fn set(&mut self, m: usize, x: N) {
+        match self {
+            Storage::Memory(g) => g[m] = x,
+            Storage::Mapped(g) => g[m] = x,
+            Storage::Packed(g) => g.set(m, x),
+        }
+    }
+
+    /// The nimbers as a slice, unless they are packed.
+    pub fn as_slice(&self) -> Option<&[N]> {
+        match self {
+            Storage::Memory(g) => Some(g),
+            Storage::Mapped(g) => Some(g),
+            Storage::Packed(_) => None,
+        }
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = N> + '_ {
+        (0..self.len()).map(|m| self.get(m))
+    }
+
+    pub fn to_vec(&self) -> Vec<N> {
+        match self.as_slice() {
+            Some(g) => g.to_vec(),
+            None => self.iter().collect(),
+        }
+    }
+
+    /// Replace all the nimbers, keeping them in the same storage.
+    pub fn load(&mut self, g: &[N]) {
+        match self {
+            Storage::Memory(front) => front.copy_from_slice(g),
+            Storage::Mapped(front) => front.copy_from_slice(g),
+            Storage::Packed(front) => *front = Packed::from_slice(g),
+        }
+    }
+
+    /// Keep the nimbers bit-packed from now on, they are repacked with more bits when needed.
+    ///
+    /// The nimbers which were not computed yet (N::MAX) become 0, so that they do not take all
+    /// the bits of N.
+    pub fn pack(&mut self) {
+        if let Storage::Packed(_) = self {
+            return;
+        }
+        let g: Vec<N> = self
+            .iter()
+            .map(|x| if x == N::MAX { N::from_usize(0) } else { x })
+            .collect();
+        *self = Storage::Packed(Packed::from_slice(&g));
+    }
+
+    /// Convert the nimbers to a wider type.
+    ///
+    /// A mapped front is rewritten with the wider nimbers, and panics if that fails, as the
+    /// computation cannot continue without it.
+    pub fn widen<M: Nimber>(self) -> Storage<M> {
+        match self {
+            Storage::Memory(g) => {
+                Storage::Memory(g.into_iter().map(|x| M::from_usize(x.to_usize())).collect())
+            }
+            Storage::Mapped(g) => {
+                let path = g.path().to_path_buf();
+                Storage::Mapped(
+                    g.widen().unwrap_or_else(|e| {
+                        panic!("unable to widen the front in {:?}: {}", path, e)
+                    }),
+                )
+            }
+            Storage::Packed(g) => Storage::Packed(g.widen()),
+        }
+    }
+
+    /// Record that the first `computed` nimbers are final, writing them to the disk if they are
+    /// mapped.
+    pub fn sync(&mut self, computed: usize) -> io::Result<()> {
+        match self {
+            Storage::Mapped(g) => g.sync(computed),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The nimbers of the front or the tail ring as the stores read them: a plain slice, or a
+/// `Storage`, which looks where the nimbers are kept at every read.
+///
+/// The loops over the pairs of heaps read the slice whenever the nimbers are not packed, so that
+/// they compile to plain indexing.
+pub trait Values<N: Nimber> {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, m: usize) -> N;
+
+    fn as_slice(&self) -> Option<&[N]>;
+}
+
+impl<N: Nimber> Values<N> for [N] {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        <[N]>::len(self)
+    }
+
+    #[inline(always)]
+    fn get(&self, m: usize) -> N {
+        self[m]
+    }
+
+    fn as_slice(&self) -> Option<&[N]> {
+        Some(self)
+    }
+}
+
+impl<N: Nimber> Values<N> for Storage<N> {
+    fn len(&self) -> usize {
+        Storage::len(self)
+    }
+
+    #[inline(always)]
+    fn get(&self, m: usize) -> N {
+        Storage::get(self, m)
+    }
+
+    fn as_slice(&self) -> Option<&[N]> {
+        Storage::as_slice(self)
+    }
+}
+
+impl<N: Nimber> PartialEq for Storage<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+// compared with nimbers as a Vec<N> is
+impl<N: Nimber> PartialEq<[N]> for Storage<N> {
+    fn eq(&self, other: &[N]) -> bool {
+        self.iter().eq(other.iter().copied())
+    }
+}
+
+impl<N: Nimber> PartialEq<Vec<N>> for Storage<N> {
+    fn eq(&self, other: &Vec<N>) -> bool {
+        *self == **other
+    }
+}
+
+impl<N: Nimber, const K: usize> PartialEq<&[N; K]> for Storage<N> {
+    fn eq(&self, other: &&[N; K]) -> bool {
+        *self == other[..]
+    }
+}
+
+impl<N: Nimber> fmt::Debug for Storage<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,380 @@
+use super::storage::Values;
+use super::{Nimber, Storage};
+use std::ops::Range;
+
+/// Read access to the values of the heaps, wherever they are kept.
+///
+/// The front, the tail ring and the rings saved on disk (`History`) are stores, and `Chain` joins
+/// them into everything the computation of a heap can read, so the same routines compute the
+/// heaps in front and in tail mode.
+pub trait NimberStore<N: Nimber> {
+    /// The heaps held one after another around m, or an empty range if m is not held.
+    fn held(&self, m: usize) -> Range<usize>;
+
+    /// The value of the heap m, which has to be held.
+    fn get(&self, m: usize) -> N;
+
+    /// The values of the heaps m, m + 1, ... as a slice, if the store has one.
+    fn forward(&self, _m: usize) -> Option<&[N]> {
+        None
+    }
+
+    /// The values of the heaps m, m - 1, ... as a slice, if the store keeps them reversed.
+    fn backward(&self, _m: usize) -> Option<&[N]> {
+        None
+    }
+
+    #[inline(always)]
+    fn holds(&self, m: usize) -> bool {
+        self.held(m).contains(&m)
+    }
+}
+
+impl<N: Nimber> NimberStore<N> for Storage<N> {
+    fn held(&self, m: usize) -> Range<usize> {
+        if m < self.len() {
+            0..self.len()
+        } else {
+            m..m
+        }
+    }
+
+    #[inline(always)]
+    fn get(&self, m: usize) -> N {
+        Storage::get(self, m)
+    }
+
+    fn forward(&self, m: usize) -> Option<&[N]> {
+        self.as_slice().map(|g| &g[m..])
+    }
+}
+
+/// The front (g), with its reversed copy if it is kept.
+///
+/// G is the `Storage` of the front, or its slice when it has one (see `Values`).
+pub struct Front<'a, N: Nimber, G: ?Sized = Storage<N>> {
+    g: &'a G,
+    rev: &'a [N],
+    /// g.len(), which `Storage` finds with a match
+    len: usize,
+}
+
+impl<N: Nimber, G: ?Sized> Clone for Front<'_, N, G> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<N: Nimber, G: ?Sized> Copy for Front<'_, N, G> {}
+
+impl<'a, N: Nimber, G: Values<N> + ?Sized> Front<'a, N, G> {
+    /// rev is empty unless the reversed copies are kept (`Nimbers::use_reversed`).
+    pub fn new(g: &'a G, rev: &'a [N]) -> Self {
+        Self {
+            g,
+            rev,
+            len: g.len(),
+        }
+    }
+}
+
+impl<N: Nimber, G: Values<N> + ?Sized> NimberStore<N> for Front<'_, N, G> {
+    #[inline(always)]
+    fn held(&self, m: usize) -> Range<usize> {
+        if m < self.len {
+            0..self.len
+        } else {
+            m..m
+        }
+    }
+
+    #[inline(always)]
+    fn get(&self, m: usize) -> N {
+        self.g.get(m)
+    }
+
+    fn forward(&self, m: usize) -> Option<&[N]> {
+        if self.rev.is_empty() {
+            return None;
+        }
+        self.g.as_slice().map(|g| &g[m..])
+    }
+
+    fn backward(&self, m: usize) -> Option<&[N]> {
+        if self.rev.is_empty() {
+            return None;
+        }
+        Some(&self.rev[self.rev.len() - 1 - m..])
+    }
+}
+
+/// The tail ring (g_back) while computing the heap `end`: it holds the heaps
+/// end - g_back.len()..end, the heap m at m % g_back.len().
+pub struct Ring<'a, N: Nimber, G: ?Sized = Storage<N>> {
+    g_back: &'a G,
+    rev: &'a [N],
+    len: usize,
+    end: usize,
+    /// The first heap held, and where it is in the ring, so that `get` does not divide.
+    first: usize,
+    first_loc: usize,
+}
+
+impl<N: Nimber, G: ?Sized> Clone for Ring<'_, N, G> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<N: Nimber, G: ?Sized> Copy for Ring<'_, N, G> {}
+
+impl<'a, N: Nimber, G: Values<N> + ?Sized> Ring<'a, N, G> {
+    /// rev is empty unless the reversed copies are kept.
+    pub fn new(g_back: &'a G, rev: &'a [N], end: usize) -> Self {
+        let first = end.saturating_sub(g_back.len());
+        Self {
+            g_back,
+            rev,
+            len: g_back.len(),
+            end,
+            first,
+            // no heaps at all in a ring of length 0
+            first_loc: first.checked_rem(g_back.len()).unwrap_or(0),
+        }
+    }
+
+    #[inline(always)]
+    fn loc(&self, m: usize) -> usize {
+        let loc = m - self.first + self.first_loc;
+        if loc >= self.len {
+            loc - self.len
+        } else {
+            loc
+        }
+    }
+}
+
+impl<N: Nimber, G: Values<N> + ?Sized> NimberStore<N> for Ring<'_, N, G> {
+    #[inline(always)]
+    fn held(&self, m: usize) -> Range<usize> {
+        if (self.first..self.end).contains(&m) {
+            self.first..self.end
+        } else {
+            m..m
+        }
+    }
+
+    #[inline(always)]
+    fn get(&self, m: usize) -> N {
+        self.g_back.get(self.loc(m))
+    }
+
+    fn forward(&self, m: usize) -> Option<&[N]> {
+        if self.rev.is_empty() {
+            return None;
+        }
+        // up to the end of the ring or the last heap held, whichever comes first
+        let loc = self.loc(m);
+        let len = std::cmp::min(self.len - loc, self.end - m);
+        self.g_back.as_slice().map(|g| &g[loc..loc + len])
+    }
+
+    fn backward(&self, m: usize) -> Option<&[N]> {
+        if self.rev.is_empty() {
+            return None;
+        }
+        // down to the start of the ring or the first heap held, whichever comes first
+        let loc = self.loc(m);
+        let len = std::cmp::min(loc + 1, m + 1 - self.first);
+        let start = self.rev.len() - 1 - loc;
+        Some(&self.rev[start..start + len])
+    }
+}
+
+/// The heaps held by either store, read from the first one when both hold them.
+#[derive(Clone, Copy)]
+pub struct Chain<A, B>(pub A, pub B);
+
+impl<N: Nimber, A: NimberStore<N>, B: NimberStore<N>> NimberStore<N> for Chain<A, B> {
+    fn held(&self, m: usize) -> Range<usize> {
+        let (a, b) = (self.0.held(m), self.1.held(m));
+        if a.is_empty() {
+            b
+        } else if b.is_empty() {
+            a
+        } else {
+            // both hold m, so the ranges overlap
+            std::cmp::min(a.start, b.start)..std::cmp::max(a.end, b.end)
+        }
+    }
+
+    #[inline(always)]
+    fn get(&self, m: usize) -> N {
+        if self.0.holds(m) {
+            self.0.get(m)
+        } else {
+            self.1.get(m)
+        }
+    }
+
+    fn forward(&self, m: usize) -> Option<&[N]> {
+        if self.0.holds(m) {
+            self.0.forward(m)
+        } else {
+            self.1.forward(m)
+        }
+    }
+
+    fn backward(&self, m: usize) -> Option<&[N]> {
+        if self.0.holds(m) {
+            self.0.backward(m)
+        } else {
+            self.1.backward(m)
+        }
+    }
+}
+
+impl<N: Nimber, S: NimberStore<N> + ?Sized> NimberStore<N> for &S {
+    fn held(&self, m: usize) -> Range<usize> {
+        (**self).held(m)
+    }
+
+    #[inline(always)]
+    fn get(&self, m: usize) -> N {
+        (**self).get(m)
+    }
+
+    fn forward(&self, m: usize) -> Option<&[N]> {
+        (**self).forward(m)
+    }
+
+    fn backward(&self, m: usize) -> Option<&[N]> {
+        (**self).backward(m)
+    }
+}
+
+// a store which may be missing, like the history when the rings are not saved
+impl<N: Nimber, S: NimberStore<N>> NimberStore<N> for Option<S> {
+    fn held(&self, m: usize) -> Range<usize> {
+        match self {
+            Some(store) => store.held(m),
+            None => m..m,
+        }
+    }
+
+    fn get(&self, m: usize) -> N {
+        match self {
+            Some(store) => store.get(m),
+            None => panic!("G({}) is neither in the front nor in the tail ring", m),
+        }
+    }
+
+    fn forward(&self, m: usize) -> Option<&[N]> {
+        self.as_ref().and_then(|store| store.forward(m))
+    }
+
+    fn backward(&self, m: usize) -> Option<&[N]> {
+        self.as_ref().and_then(|store| store.backward(m))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stores() {
+        let values: Vec<u16> = (0..40).map(|m| (m * 7 % 13) as u16).collect();
+        let g = Storage::Memory(values[..16].to_vec());
+        let g_rev: Vec<u16> = values[..16].iter().rev().copied().collect();
+
+        // the ring of 8 heaps while computing the heap 30 holds the heaps 22..30
+        let mut ring = vec![0; 8];
+        for m in 22..30 {
+            ring[m % 8] = values[m];
+        }
+        let ring_rev: Vec<u16> = ring.iter().rev().copied().collect();
+        let g_back = Storage::Memory(ring);
+
+        for (rev, ring_rev) in [(&g_rev[..], &ring_rev[..]), (&[][..], &[][..])] {
+            let front = Front::new(&g, rev);
+            let tail = Ring::new(&g_back, ring_rev, 30);
+            let store = Chain(front, tail);
+
+            assert_eq!(store.held(3), 0..16);
+            assert_eq!(store.held(25), 22..30);
+            assert!(store.held(20).is_empty() && store.held(30).is_empty());
+            for m in (0..16).chain(22..30) {
+                assert_eq!(store.get(m), values[m]);
+            }
+
+            // the slices stop where the heaps held or the ring end
+            for m in (0..16).chain(22..30) {
+                if let Some(a) = store.forward(m) {
+                    assert!(
+                        !a.is_empty() && a.iter().enumerate().all(|(k, &x)| x == values[m + k])
+                    );
+                }
+                if let Some(b) = store.backward(m) {
+                    assert!(
+                        !b.is_empty() && b.iter().enumerate().all(|(k, &x)| x == values[m - k])
+                    );
+                }
+            }
+            assert_eq!(store.forward(1).is_some(), !rev.is_empty());
+            assert_eq!(
+                store.backward(29).map(<[u16]>::len),
+                Some(6).filter(|_| !rev.is_empty())
+            );
+            assert_eq!(
+                store.backward(23).map(<[u16]>::len),
+                Some(2).filter(|_| !rev.is_empty())
+            );
+        }
+
+        // the same stores over the slices of the storage
+        let g_slice = g.as_slice().unwrap();
+        let store = Chain(
+            Front::new(g_slice, &g_rev),
+            Ring::new(g_back.as_slice().unwrap(), &ring_rev, 30),
+        );
+        for m in (0..16).chain(22..30) {
+            assert_eq!(store.get(m), values[m]);
+        }
+        assert_eq!(store.forward(3), Some(&values[3..16]));
+
+        assert!(None::<&Storage<u16>>.held(3).is_empty());
+    }
+
+    #[test]
+    fn test_ring_pairs() {
+        // the pairs (j, m - j) of the heaps m = n - i read in tail mode while computing the heap
+        // n, for every position of n in the ring: the partner of the heap j is the heap m - j,
+        // never the heap m - j + 1 (the heap n itself, not computed yet, when i == 0)
+        let values: Vec<u16> = (0..64).map(|m| (m * 11 % 17) as u16).collect();
+        let g = Storage::Memory(values[..16].to_vec());
+
+        for n in 24..40 {
+            let mut ring = vec![u16::MAX; 8];
+            for m in n - 8..n {
+                ring[m % 8] = values[m];
+            }
+            let g_back = Storage::Memory(ring);
+            let store = Chain(Front::new(&g, &[]), Ring::new(&g_back, &[], n));
+
+            assert!(!store.holds(n));
+            for m in [n, n - 1, n - 2] {
+                for j in (1..=m / 2).filter(|&j| store.holds(j) && store.holds(m - j)) {
+                    assert_eq!(
+                        store.get(j) ^ store.get(m - j),
+                        values[j] ^ values[m - j],
+                        "pair ({}, {}) while computing {}",
+                        j,
+                        m - j,
+                        n
+                    );
+                }
+            }
+        }
+    }
+}
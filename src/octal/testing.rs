@@ -0,0 +1,89 @@
+//! Fixtures shared by the tests of the solver.
+
+use super::{BitSet, Game, Nimber};
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// A directory for the files of a test, removed with all of them when dropped, also when the
+/// test fails.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("octal_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The game computed in the front up to max, after `configure` set it up.
+pub fn front<N: Nimber, B: BitSet>(
+    rules_str: &str,
+    max: usize,
+    configure: impl FnOnce(&mut Game<N, B>),
+) -> Game<N, B> {
+    let mut g = Game::new(rules_str.parse().unwrap(), max, 0);
+    configure(&mut g);
+    g.init();
+    for n in g.rules.len()..max {
+        g.calc_rc(n).unwrap();
+    }
+    g
+}
+
+/// The game computed in the front up to max_full_memory and in tail mode up to max, after
+/// `configure` set it up, checking every value with the one found by trying every move.
+///
+/// The front is saved in dir, and the ring of max_full_memory heaps with a checkpoint after each
+/// pass over it, so the game can be resumed from there.
+pub fn check_against_naive<N: Nimber, B: BitSet>(
+    rules_str: &str,
+    max_full_memory: usize,
+    max: usize,
+    dir: &Path,
+    configure: impl FnOnce(&mut Game<N, B>),
+) -> Game<N, B> {
+    let mut naive = Game::<u16>::new(rules_str.parse().unwrap(), max, 0);
+    naive.init();
+    for n in naive.rules.len()..max {
+        naive.calc_naive(n).unwrap();
+    }
+    let expected = |n: usize| naive.nimbers.g.get(n).to_usize();
+
+    let mut g = Game::new(rules_str.parse().unwrap(), max_full_memory, max_full_memory);
+    configure(&mut g);
+    g.init();
+    for n in g.rules.len()..max_full_memory {
+        g.calc_rc(n).unwrap();
+        let nim = g.nimbers.g.get(n).to_usize();
+        assert_eq!(nim, expected(n), "for game {} at {}", rules_str, n);
+    }
+
+    g.nimbers.copy_to_g_back();
+    g.save_front(dir).unwrap();
+    g.use_history(dir);
+    for n in max_full_memory..max {
+        if n % max_full_memory == 0 {
+            g.save_checkpoint(dir, n).unwrap();
+        }
+        g.calc_rc_back(n).unwrap();
+        let nim = g.nimbers.last(n).to_usize();
+        assert_eq!(nim, expected(n), "for game {} at {}", rules_str, n);
+    }
+    g
+}